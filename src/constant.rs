@@ -2,3 +2,7 @@ pub const GLOBAL_ADMIN: &'static [i64] = &[
 ];
 
 pub const TELEGRAM_MAX_MSG_LEN: usize = 4096;
+pub const TELEGRAM_MAX_CAPTION_LEN: usize = 1024;
+pub const TELEGRAM_MAX_ALBUM_LEN: usize = 10;
+
+pub const EXCERPT_MAX_LEN: usize = 280;
@@ -1,12 +1,19 @@
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs::File;
 use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::mem;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::time::{Duration, SystemTime};
 
+use chrono::{DateTime, Utc};
+use ring::digest;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use thiserror::Error;
+use url::Url;
 
 use crate::feed;
 
@@ -18,9 +25,26 @@ pub enum DataError {
     Json(#[from] serde_json::Error),
 }
 
-fn gen_hash<T: Hash>(t: &T) -> u64 {
+/// Version of `gen_hash` the hashes of a feed are made with,
+/// 0 is the `DefaultHasher` used before, which may change between Rust releases
+const HASH_VERSION: u32 = 1;
+/// A permanent redirect is followed once it's been seen this often, for this long
+const REDIRECT_CONFIRMATIONS: u32 = 3;
+const REDIRECT_CONFIRMATION_TIME: Duration = Duration::from_secs(24 * 60 * 60);
+/// Hashes kept at most for a feed which isn't read completely or pushed to
+const MAX_HISTORY: usize = 1000;
+
+/// The first 8 bytes of the SHA-256 of the string, big-endian
+fn gen_hash(s: &str) -> u64 {
+    let digest = digest::digest(&digest::SHA256, s.as_bytes());
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest.as_ref()[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+fn gen_legacy_hash(s: &str) -> u64 {
     let mut hasher = std::collections::hash_map::DefaultHasher::default();
-    t.hash(&mut hasher);
+    s.hash(&mut hasher);
     hasher.finish()
 }
 
@@ -33,6 +57,27 @@ pub struct FeedSettings {
     pub link_only: Option<bool>,
     pub hide_rss_title: Option<bool>,
     pub combine_msg: Option<bool>,
+    pub excerpt: Option<bool>,
+    pub send_media: Option<bool>,
+    pub hashtags: Option<bool>,
+    /// Items older than this many days are not pushed, 0 is unlimited
+    pub max_age: Option<u32>,
+    pub subscribed_at: Option<SystemTime>,
+    /// Edit the messages of changed items and delete those of removed ones,
+    /// items are not combined then
+    pub edit_msg: Option<bool>,
+}
+
+impl FeedSettings {
+    /// Items published before the cutoff will never be pushed
+    pub fn cutoff(&self) -> Option<DateTime<Utc>> {
+        let by_age = self
+            .max_age
+            .filter(|&days| days > 0)
+            .map(|days| Utc::now() - chrono::Duration::days(days.into()));
+        let by_subscription = self.subscribed_at.map(DateTime::<Utc>::from);
+        by_age.max(by_subscription)
+    }
 }
 
 pub fn get_combined_feed_settings(settings: Option<FeedSettings>) -> FeedSettings {
@@ -42,6 +87,12 @@ pub fn get_combined_feed_settings(settings: Option<FeedSettings>) -> FeedSetting
         link_only: Some(before.link_only.unwrap_or(false)),
         hide_rss_title: Some(before.hide_rss_title.unwrap_or(false)),
         combine_msg: Some(before.combine_msg.unwrap_or(true)),
+        excerpt: Some(before.excerpt.unwrap_or(false)),
+        send_media: Some(before.send_media.unwrap_or(false)),
+        hashtags: Some(before.hashtags.unwrap_or(false)),
+        max_age: Some(before.max_age.unwrap_or(0)),
+        subscribed_at: before.subscribed_at,
+        edit_msg: Some(before.edit_msg.unwrap_or(false)),
     }
 }
 
@@ -53,13 +104,202 @@ pub struct Feed {
     pub subscribers: HashSet<SubscriberId, Size64>,
     pub ttl: Option<u32>,
     hash_list: Vec<u64>,
+    /// `HASH_VERSION` of `hash_list`
+    #[serde(default)]
+    hash_version: u32,
+    /// Shared by all subscribers, like `hash_list`
+    #[serde(default)]
+    pub dedup: Dedup,
+    /// Items sent to subscribers who want edits, by their hashes
+    #[serde(default)]
+    item_records: HashMap<u64, ItemRecord, Size64>,
     pub settings: Option<HashMap<SubscriberId, FeedSettings, Size64>>,
+    pub hub: Option<Hub>,
+    /// For conditional requests, from the last fetch with a body
+    #[serde(default)]
+    pub validators: Validators,
+    /// Secrets may be in it, so it's never shown
+    #[serde(default)]
+    pub request: RequestOptions,
+    /// Who set `request`, the only one who may change it
+    #[serde(default)]
+    pub request_owner: Option<SubscriberId>,
+    /// Why the feed was only partly read last time
+    pub warning: Option<String>,
+    /// Text of the selection of a diff watch, as of the last fetch
+    pub snapshot: Option<String>,
+    /// Where the feed has been permanently redirected to lately
+    pub redirect: Option<Redirect>,
+    /// Subscribers were told the feed is gone, until it's fetched again
+    #[serde(default)]
+    pub gone: bool,
+    /// Not fetched again before it, as asked by the last response
+    #[serde(skip)]
+    pub next_fetch: Option<SystemTime>,
 }
 
+impl Feed {
+    /// The hub pushes updates, polling is only a fallback
+    pub fn has_websub_lease(&self) -> bool {
+        self.hub
+            .as_ref()
+            .and_then(|hub| hub.expires_at)
+            .map_or(false, |t| t > SystemTime::now())
+    }
+
+    /// Rebuild `hash_list` with `dedup` and the current `gen_hash`,
+    /// from the items of a fetch which match the old hashes
+    fn rehash(&mut self, items: &[feed::Item], dedup: Dedup) {
+        let mut records = HashMap::default();
+        let mut seen = Vec::new();
+        for item in items {
+            let key = item_key(item, &self.dedup);
+            // hashes pushed by a hub after loading a legacy list are new already
+            let old_hash = Some(gen_hash(&key))
+                .into_iter()
+                .chain(Some(gen_legacy_hash(&key)).filter(|_| self.hash_version < HASH_VERSION))
+                .find(|hash| self.hash_list.contains(hash));
+            if let Some(old_hash) = old_hash {
+                let hash = gen_item_hash(item, &dedup);
+                seen.push(hash);
+                if let Some(record) = self.item_records.remove(&old_hash) {
+                    records.insert(hash, record);
+                }
+            }
+        }
+        self.item_records = records;
+        self.hash_list = if seen.is_empty() {
+            // nothing matches if the old hashes can't be made again, like after
+            // a Rust release or with rotating ids, pushing the whole feed again
+            // is worse than missing a few items
+            items
+                .iter()
+                .map(|item| gen_item_hash(item, &dedup))
+                .collect()
+        } else {
+            seen
+        };
+        self.hash_version = HASH_VERSION;
+        self.dedup = dedup;
+    }
+
+    /// Replace the legacy hashes of `items` in place, the rest of the list
+    /// can only be rebuilt from a complete feed
+    fn rehash_partly(&mut self, items: &[feed::Item]) {
+        for item in items {
+            let legacy_hash = gen_legacy_hash(&item_key(item, &self.dedup));
+            if let Some(i) = self.hash_list.iter().position(|&h| h == legacy_hash) {
+                let hash = gen_item_hash(item, &self.dedup);
+                self.hash_list[i] = hash;
+                if let Some(record) = self.item_records.remove(&legacy_hash) {
+                    self.item_records.insert(hash, record);
+                }
+            }
+        }
+    }
+}
+
+/// WebSub subscription of a feed
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Hub {
+    pub link: String,
+    pub topic: String,
     pub callback: String,
     pub secret: String,
+    pub requested_at: Option<SystemTime>,
+    /// `None` until the hub verified the subscription
+    pub expires_at: Option<SystemTime>,
+}
+
+/// A permanent redirect seen at every fetch since `since`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Redirect {
+    pub link: String,
+    pub since: SystemTime,
+    pub count: u32,
+}
+
+/// A feed moved by `Database::move_feed`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovedFeed {
+    /// Who has to set the request options again, they are never
+    /// sent to another host, nor kept for another feed
+    pub options_dropped_for: Option<SubscriberId>,
+}
+
+/// `ETag` and `Last-Modified` of a feed response
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Options of the requests for a private feed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RequestOptions {
+    pub auth: Option<Auth>,
+    /// Replace the default ones of the same name, `Cookie` is one of them
+    pub headers: Vec<(String, String)>,
+    pub user_agent: Option<String>,
+    /// Instead of the global ones, `client::DIRECT` is none
+    pub proxy: Option<String>,
+    /// PEM root certificates, trusted besides the global ones
+    pub ca_cert: Option<String>,
+    /// PEM certificate chain for mutual TLS, instead of the global one,
+    /// with the private key, or with it in `client_key`
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Auth {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Bearer(String),
+}
+
+impl Auth {
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Auth::Basic { .. } => "basic",
+            Auth::Bearer(_) => "bearer",
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("expected basic <username>:<password> or bearer <token>")]
+pub struct InvalidAuth;
+
+impl FromStr for Auth {
+    type Err = InvalidAuth;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(2, char::is_whitespace);
+        let scheme = parts.next().unwrap_or_default().to_ascii_lowercase();
+        let credentials = parts.next().map(str::trim).unwrap_or_default();
+        match &*scheme {
+            _ if credentials.is_empty() => Err(InvalidAuth),
+            "basic" => {
+                let mut credentials = credentials.splitn(2, ':');
+                Ok(Auth::Basic {
+                    username: credentials.next().unwrap().to_owned(),
+                    password: credentials.next().map(str::to_owned),
+                })
+            }
+            "bearer" => Ok(Auth::Bearer(credentials.to_owned())),
+            _ => Err(InvalidAuth),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -67,6 +307,9 @@ pub struct Database {
     path: PathBuf,
     feeds: HashMap<FeedId, Feed, Size64>,
     subscribers: HashMap<SubscriberId, HashSet<FeedId, Size64>, Size64>,
+    /// Set before subscribing, the feed can't be fetched without them,
+    /// not saved, they are moved into the feed at subscription
+    pending_requests: HashMap<(SubscriberId, FeedId), RequestOptions>,
 }
 
 impl Database {
@@ -75,6 +318,7 @@ impl Database {
             path,
             feeds: HashMap::with_hasher(Size64::default()),
             subscribers: HashMap::with_hasher(Size64::default()),
+            pending_requests: HashMap::new(),
         };
 
         result.save()?;
@@ -118,6 +362,7 @@ impl Database {
                 path,
                 feeds,
                 subscribers,
+                pending_requests: HashMap::new(),
             })
         } else {
             Database::create(path)
@@ -128,10 +373,23 @@ impl Database {
         self.feeds.iter().map(|(_, v)| v.clone()).collect()
     }
 
+    /// Those of the feeds and those kept until subscribing
+    pub fn all_request_options(&self) -> Vec<RequestOptions> {
+        let feeds = self.feeds.values().map(|feed| &feed.request);
+        feeds
+            .chain(self.pending_requests.values())
+            .cloned()
+            .collect()
+    }
+
     pub fn all_subscribers(&self) -> Vec<SubscriberId> {
         self.subscribers.iter().map(|(k, _)| *k).collect()
     }
 
+    pub fn get_feed(&self, rss_link: &str) -> Option<Feed> {
+        self.feeds.get(&gen_hash(&rss_link)).cloned()
+    }
+
     pub fn subscribed_feeds(&self, subscriber: SubscriberId) -> Option<Vec<Feed>> {
         self.subscribers.get(&subscriber).map(|feeds| {
             feeds
@@ -184,21 +442,48 @@ impl Database {
             }
         }
         {
+            let pending = self.pending_requests.remove(&(subscriber, feed_id));
+            let is_new = !self.feeds.contains_key(&feed_id);
             let feed = self.feeds.entry(feed_id).or_insert_with(|| Feed {
                 link: rss_link.to_owned(),
                 title: rss.title.to_owned(),
                 down_time: None,
                 ttl: rss.ttl,
-                hash_list: rss.items.iter().map(gen_item_hash).collect(),
+                hash_list: rss
+                    .items
+                    .iter()
+                    .map(|item| gen_item_hash(item, &Dedup::default()))
+                    .collect(),
+                hash_version: HASH_VERSION,
+                dedup: Dedup::default(),
+                item_records: HashMap::default(),
                 subscribers: HashSet::default(),
                 settings: Some(HashMap::with_hasher(Size64::default())),
+                hub: None,
+                validators: Validators::default(),
+                request: RequestOptions::default(),
+                request_owner: None,
+                warning: rss.warning.clone(),
+                snapshot: None,
+                redirect: None,
+                gone: false,
+                next_fetch: None,
             });
+            // they are the ones the feed was just fetched with, but the
+            // options of a feed shared with others are never replaced
+            if let (true, Some(request)) = (is_new, pending) {
+                feed.request = request;
+                feed.request_owner = Some(subscriber);
+            }
             feed.subscribers.insert(subscriber);
             feed.settings
                 .as_mut()
                 .unwrap()
                 .entry(subscriber.to_owned())
-                .or_insert_with(FeedSettings::default);
+                .or_insert_with(|| FeedSettings {
+                    subscribed_at: Some(SystemTime::now()),
+                    ..FeedSettings::default()
+                });
         }
         self.save().unwrap_or_default();
         true
@@ -227,6 +512,11 @@ impl Database {
             feed.settings.as_mut().unwrap().remove(&subscriber);
             if feed.subscribers.remove(&subscriber) {
                 clear_feed = feed.subscribers.is_empty();
+                if feed.request_owner == Some(subscriber) {
+                    // secrets of theirs are not for the others
+                    feed.request = RequestOptions::default();
+                    feed.request_owner = None;
+                }
                 result = feed.clone();
             } else {
                 return None;
@@ -263,6 +553,9 @@ impl Database {
                     let setting = settings.get(&from).unwrap().clone();
                     settings.remove(&from);
                     settings.insert(to, setting);
+                    if feed.request_owner == Some(from) {
+                        feed.request_owner = Some(to);
+                    }
                 }
                 self.subscribers.insert(to, feeds);
             })
@@ -315,8 +608,282 @@ impl Database {
         true
     }
 
+    pub fn set_hub(&mut self, rss_link: &str, hub: Option<Hub>) -> bool {
+        let feed_id = gen_hash(&rss_link);
+        if let Some(feed) = self.feeds.get_mut(&feed_id) {
+            feed.hub = hub;
+        } else {
+            return false;
+        }
+        self.save().unwrap_or_default();
+        true
+    }
+
+    pub fn set_validators(&mut self, rss_link: &str, validators: Validators) -> bool {
+        let feed_id = gen_hash(&rss_link);
+        match self.feeds.get_mut(&feed_id) {
+            Some(feed) if feed.validators == validators => return true,
+            Some(feed) => feed.validators = validators,
+            None => return false,
+        }
+        self.save().unwrap_or_default();
+        true
+    }
+
+    /// Not saved, it's only about the next fetch
+    pub fn set_next_fetch(&mut self, rss_link: &str, next_fetch: Option<SystemTime>) -> bool {
+        let feed_id = gen_hash(&rss_link);
+        match self.feeds.get_mut(&feed_id) {
+            Some(feed) => {
+                feed.next_fetch = next_fetch;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Return whether it changed, so subscribers are told only once
+    pub fn set_gone(&mut self, rss_link: &str, gone: bool) -> bool {
+        let feed_id = gen_hash(&rss_link);
+        match self.feeds.get_mut(&feed_id) {
+            Some(feed) if feed.gone != gone => {
+                feed.gone = gone;
+                self.save().unwrap_or_default();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Track the permanent redirect of a fetch, `None` if there's none,
+    /// return the new link once the redirect can be trusted
+    pub fn track_redirect(&mut self, rss_link: &str, moved_to: Option<&str>) -> Option<String> {
+        let feed = self.feeds.get_mut(&gen_hash(&rss_link))?;
+        let redirect = match (moved_to, &mut feed.redirect) {
+            (None, None) => return None,
+            (None, redirect) => {
+                *redirect = None;
+                self.save().unwrap_or_default();
+                return None;
+            }
+            (Some(link), Some(redirect)) if redirect.link == link => {
+                redirect.count += 1;
+                redirect.clone()
+            }
+            (Some(link), redirect) => {
+                // a new target starts over
+                let new = Redirect {
+                    link: link.to_owned(),
+                    since: SystemTime::now(),
+                    count: 1,
+                };
+                *redirect = Some(new.clone());
+                new
+            }
+        };
+        self.save().unwrap_or_default();
+        let elapsed = redirect.since.elapsed().unwrap_or_default();
+        if redirect.count >= REDIRECT_CONFIRMATIONS && elapsed >= REDIRECT_CONFIRMATION_TIME {
+            Some(redirect.link)
+        } else {
+            None
+        }
+    }
+
+    /// Move a feed to a new link, with its subscribers, it's merged into
+    /// the feed of the new link if there's one already, its request options
+    /// only go along to the same host
+    pub fn move_feed(&mut self, rss_link: &str, new_link: &str) -> Option<MovedFeed> {
+        let feed_id = gen_hash(&rss_link);
+        let new_id = gen_hash(&new_link);
+        if feed_id == new_id {
+            return None;
+        }
+        let mut feed = self.feeds.remove(&feed_id)?;
+        for subscriber in &feed.subscribers {
+            if let Some(feeds) = self.subscribers.get_mut(subscriber) {
+                feeds.remove(&feed_id);
+                feeds.insert(new_id);
+            }
+        }
+        feed.redirect = None;
+        // its topic is the old link
+        feed.hub = None;
+        let host = |link| {
+            Url::parse(link)
+                .ok()
+                .map(|url| url.host_str().map(str::to_owned))
+        };
+        let merged = self.feeds.contains_key(&new_id);
+        let mut moved = MovedFeed {
+            options_dropped_for: None,
+        };
+        if merged || host(rss_link) != host(new_link) {
+            // like the credentials of a redirect to another host
+            if feed.request != RequestOptions::default() {
+                moved.options_dropped_for = feed.request_owner;
+            }
+            feed.request = RequestOptions::default();
+            feed.request_owner = None;
+        }
+        match self.feeds.get_mut(&new_id) {
+            Some(existing) => {
+                // the settings of those subscribed to both are kept
+                let settings = existing.settings.get_or_insert_with(Default::default);
+                for (subscriber, setting) in feed.settings.unwrap_or_default() {
+                    settings.entry(subscriber).or_insert(setting);
+                }
+                existing.subscribers.extend(feed.subscribers);
+            }
+            None => {
+                feed.link = new_link.to_owned();
+                self.feeds.insert(new_id, feed);
+            }
+        }
+        self.save().unwrap_or_default();
+        Some(moved)
+    }
+
+    /// Options to fetch a feed for a subscriber, those of the feed
+    /// are only for its subscribers
+    pub fn request_options(&self, subscriber: SubscriberId, rss_link: &str) -> RequestOptions {
+        let feed_id = gen_hash(&rss_link);
+        if self.is_subscribed(subscriber, rss_link) {
+            return self.feeds[&feed_id].request.clone();
+        }
+        self.pending_requests
+            .get(&(subscriber, feed_id))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Kept until subscribing if not subscribed yet, refused for a feed
+    /// with other subscribers
+    pub fn set_request_options(
+        &mut self,
+        subscriber: SubscriberId,
+        rss_link: &str,
+        options: RequestOptions,
+    ) -> bool {
+        let feed_id = gen_hash(&rss_link);
+        if !self.is_subscribed(subscriber, rss_link) {
+            self.pending_requests.insert((subscriber, feed_id), options);
+            return true;
+        }
+        let feed = self.feeds.get_mut(&feed_id).unwrap();
+        if feed.subscribers.iter().any(|&id| id != subscriber) {
+            return false;
+        }
+        feed.request_owner = if options == RequestOptions::default() {
+            None
+        } else {
+            Some(subscriber)
+        };
+        feed.request = options;
+        self.save().unwrap_or_default();
+        true
+    }
+
+    pub fn feed_by_hub_callback(&self, callback: &str) -> Option<Feed> {
+        self.feeds
+            .values()
+            .find(|feed| matches!(&feed.hub, Some(hub) if hub.callback == callback))
+            .cloned()
+    }
+
+    /// Replace the text of a diff watch, return the previous one
+    pub fn swap_snapshot(&mut self, rss_link: &str, snapshot: Option<String>) -> Option<String> {
+        let feed = self.feeds.get_mut(&gen_hash(&rss_link))?;
+        let previous = mem::replace(&mut feed.snapshot, snapshot);
+        if previous != feed.snapshot {
+            self.save().unwrap_or_default();
+        }
+        previous
+    }
+
     /// Update the feed in database, return updates
     pub fn update(&mut self, rss_link: &str, new_feed: feed::Rss) -> Vec<FeedUpdate> {
+        let complete = new_feed.warning.is_none();
+        self.migrate_hash_list(rss_link, &new_feed.items, complete);
+        if !complete {
+            // items after the error are missing, they must not be forgotten
+            let max_size = self.partial_history_size(rss_link, new_feed.items.len());
+            return self.update_with_history(rss_link, new_feed, max_size, false);
+        }
+        let max_size = new_feed.items.len() * 2;
+        self.update_with_history(rss_link, new_feed, max_size, true)
+    }
+
+    /// Like `update`, but for content pushed by a WebSub hub,
+    /// which only has the changed items, so the history is kept
+    pub fn update_pushed(&mut self, rss_link: &str, new_feed: feed::Rss) -> Vec<FeedUpdate> {
+        self.migrate_hash_list(rss_link, &new_feed.items, false);
+        let max_size = self.partial_history_size(rss_link, new_feed.items.len());
+        self.update_with_history(rss_link, new_feed, max_size, false)
+    }
+
+    /// The history of a feed only partly read or pushed to is kept,
+    /// but not beyond `MAX_HISTORY`
+    fn partial_history_size(&self, rss_link: &str, new_items: usize) -> usize {
+        let kept = self
+            .feeds
+            .get(&gen_hash(&rss_link))
+            .map_or(0, |feed| feed.hash_list.len());
+        // as much as a complete fetch keeps at least
+        kept.max(new_items * 2).min(MAX_HISTORY)
+    }
+
+    /// Remember the message an item was sent as, to edit it later
+    pub fn record_message(&mut self, rss_link: &str, item: &feed::Item, message: SentMessage) {
+        let feed = match self.feeds.get_mut(&gen_hash(&rss_link)) {
+            Some(feed) => feed,
+            None => return,
+        };
+        let hash = gen_item_hash(item, &feed.dedup);
+        feed.item_records
+            .entry(hash)
+            .or_insert_with(|| ItemRecord {
+                fingerprint: gen_fingerprint(item),
+                messages: Vec::new(),
+            })
+            .messages
+            .push(message);
+        self.save().unwrap_or_default();
+    }
+
+    /// Hashes of an older version can't be converted by themselves,
+    /// so they are rebuilt from the items of a fetch, only those of the
+    /// items if the fetch isn't `complete`, like a push
+    fn migrate_hash_list(&mut self, rss_link: &str, items: &[feed::Item], complete: bool) {
+        match self.feeds.get_mut(&gen_hash(&rss_link)) {
+            Some(feed) if feed.hash_version < HASH_VERSION && complete => {
+                feed.rehash(items, feed.dedup)
+            }
+            Some(feed) if feed.hash_version < HASH_VERSION => feed.rehash_partly(items),
+            _ => return,
+        }
+        self.save().unwrap_or_default();
+    }
+
+    /// Change how the items of a feed are told apart, `items` are the current ones,
+    /// those already pushed are not pushed again
+    pub fn set_dedup(&mut self, rss_link: &str, dedup: Dedup, items: &[feed::Item]) -> bool {
+        match self.feeds.get_mut(&gen_hash(&rss_link)) {
+            Some(feed) => feed.rehash(items, dedup),
+            None => return false,
+        }
+        self.save().unwrap_or_default();
+        true
+    }
+
+    /// Removed items are only looked for in a `complete` feed
+    fn update_with_history(
+        &mut self,
+        rss_link: &str,
+        new_feed: feed::Rss,
+        max_size: usize,
+        complete: bool,
+    ) -> Vec<FeedUpdate> {
         let feed_id = gen_hash(&rss_link);
         if !self.feeds.contains_key(&feed_id) {
             return Vec::new();
@@ -328,33 +895,63 @@ impl Database {
         let mut updates = Vec::new();
         let mut new_items = Vec::new();
         let mut new_hash_list = Vec::new();
-        let items_len = new_feed.items.len();
+        let mut edits = Vec::new();
+        let mut present = HashSet::new();
         for item in new_feed.items {
-            let hash = gen_item_hash(&item);
+            let hash = gen_item_hash(&item, &feed.dedup);
+            let is_first = present.insert(hash);
             if !feed.hash_list.contains(&hash) {
                 new_hash_list.push(hash);
                 new_items.push(item);
+            } else if let Some(record) = feed.item_records.get_mut(&hash).filter(|_| is_first) {
+                let fingerprint = gen_fingerprint(&item);
+                if record.fingerprint != fingerprint {
+                    record.fingerprint = fingerprint;
+                    edits.push(FeedUpdate::ItemEdited(item, record.messages.clone()));
+                }
+            }
+        }
+        if complete {
+            // older items drop out of a feed, only those before the oldest
+            // one still in it are removed
+            let oldest = feed
+                .hash_list
+                .iter()
+                .rposition(|hash| present.contains(hash));
+            for hash in &feed.hash_list[..oldest.unwrap_or(0)] {
+                if present.contains(hash) {
+                    continue;
+                }
+                if let Some(record) = feed.item_records.remove(hash) {
+                    edits.push(FeedUpdate::ItemRemoved(record.messages));
+                }
             }
         }
         if !new_items.is_empty() {
             updates.push(FeedUpdate::Items(new_items));
 
-            let max_size = items_len * 2;
             let mut append: Vec<u64> = feed
                 .hash_list
                 .iter()
-                .take(max_size - new_hash_list.len())
+                .take(max_size.saturating_sub(new_hash_list.len()))
                 .cloned()
                 .collect();
             new_hash_list.append(&mut append);
             feed.hash_list = new_hash_list;
+            let hash_list = &feed.hash_list;
+            feed.item_records.retain(|hash, _| hash_list.contains(hash));
         }
-        if new_feed.title != feed.title {
+        updates.append(&mut edits);
+        // the title may not be read in a broken feed
+        let title_missing = new_feed.warning.is_some() && new_feed.title.is_empty();
+        if new_feed.title != feed.title && !title_missing {
             updates.push(FeedUpdate::Title(new_feed.title.clone()));
             feed.title = new_feed.title;
         }
         feed.ttl = new_feed.ttl;
-        if !updates.is_empty() {
+        let warning_changed = feed.warning != new_feed.warning;
+        feed.warning = new_feed.warning;
+        if !updates.is_empty() || warning_changed {
             self.save().unwrap_or_default();
         }
         updates
@@ -377,14 +974,170 @@ impl Database {
 pub enum FeedUpdate {
     Items(Vec<feed::Item>),
     Title(String),
+    /// An item sent before changed, with the messages it was sent as
+    ItemEdited(feed::Item, Vec<SentMessage>),
+    /// An item disappeared while older items are still in the feed
+    ItemRemoved(Vec<SentMessage>),
+}
+
+/// What an item looked like when it was sent, and where to
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ItemRecord {
+    fingerprint: u64,
+    messages: Vec<SentMessage>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SentMessage {
+    pub chat: SubscriberId,
+    pub id: u32,
+    /// The item is in the caption of a media message
+    pub caption: bool,
+}
+
+/// Changes with what is shown of an item
+fn gen_fingerprint(item: &feed::Item) -> u64 {
+    let title = item.title.as_ref().map(|s| s.as_str()).unwrap_or_default();
+    let link = item.link.as_ref().map(|s| s.as_str()).unwrap_or_default();
+    let body = item
+        .summary
+        .as_ref()
+        .or_else(|| item.content.as_ref())
+        .map(|s| s.as_str())
+        .unwrap_or_default();
+    gen_hash(&format!("{}\n{}\n{}", title, link, body))
+}
+
+/// How the items of a feed are told apart
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dedup {
+    pub key: DedupKey,
+    /// Drop tracking parameters like `utm_source` from links before comparing them
+    #[serde(default)]
+    pub strip_tracking: bool,
+}
+
+/// The field which tells items apart, items without it use the title and link
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupKey {
+    Id,
+    Link,
+    Title,
+    TitleLink,
+    /// The content or summary
+    Content,
 }
 
-fn gen_item_hash(item: &feed::Item) -> u64 {
-    item.id.as_ref().map(|id| gen_hash(&id)).unwrap_or_else(|| {
+impl Default for DedupKey {
+    fn default() -> Self {
+        DedupKey::Id
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("expected one of id, link, title, title_link, content")]
+pub struct UnknownDedupKey;
+
+impl FromStr for DedupKey {
+    type Err = UnknownDedupKey;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "id" => Ok(DedupKey::Id),
+            "link" => Ok(DedupKey::Link),
+            "title" => Ok(DedupKey::Title),
+            "title_link" => Ok(DedupKey::TitleLink),
+            "content" => Ok(DedupKey::Content),
+            _ => Err(UnknownDedupKey),
+        }
+    }
+}
+
+impl fmt::Display for DedupKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DedupKey::Id => "id",
+            DedupKey::Link => "link",
+            DedupKey::Title => "title",
+            DedupKey::TitleLink => "title_link",
+            DedupKey::Content => "content",
+        })
+    }
+}
+
+fn gen_item_hash(item: &feed::Item, dedup: &Dedup) -> u64 {
+    gen_hash(&item_key(item, dedup))
+}
+
+/// What tells items apart, the title and link are used without the chosen field
+fn item_key<'a>(item: &'a feed::Item, dedup: &Dedup) -> Cow<'a, str> {
+    let link = || {
+        item.link.as_deref().map(|link| {
+            if dedup.strip_tracking {
+                strip_tracking_params(link)
+            } else {
+                Cow::Borrowed(link)
+            }
+        })
+    };
+    let title_link = || {
         let title = item.title.as_ref().map(|s| s.as_str()).unwrap_or_default();
-        let link = item.link.as_ref().map(|s| s.as_str()).unwrap_or_default();
-        gen_hash(&format!("{}{}", title, link))
-    })
+        Cow::Owned(format!("{}{}", title, link().unwrap_or_default()))
+    };
+    match dedup.key {
+        DedupKey::Id => item.id.as_deref().map(Cow::Borrowed),
+        DedupKey::Link => link(),
+        DedupKey::Title => item
+            .title
+            .as_deref()
+            .filter(|title| !title.is_empty())
+            .map(Cow::Borrowed),
+        DedupKey::TitleLink => None,
+        DedupKey::Content => item
+            .content
+            .as_deref()
+            .or_else(|| item.summary.as_deref())
+            .map(Cow::Borrowed),
+    }
+    .unwrap_or_else(title_link)
+}
+
+/// Links are kept as they are if they can't be parsed or have no query
+fn strip_tracking_params(link: &str) -> Cow<str> {
+    let mut url = match Url::parse(link) {
+        Ok(url) if url.query().is_some() => url,
+        _ => return Cow::Borrowed(link),
+    };
+    let is_tracking = |name: &str| {
+        name.starts_with("utm_")
+            || matches!(
+                name,
+                "fbclid"
+                    | "gclid"
+                    | "dclid"
+                    | "msclkid"
+                    | "yclid"
+                    | "igshid"
+                    | "mc_cid"
+                    | "mc_eid"
+                    | "_hsenc"
+                    | "_hsmi"
+                    | "ref_src"
+                    | "spm"
+            )
+    };
+    let params: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| !is_tracking(name))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    if params.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(params);
+    }
+    Cow::Owned(url.into_string())
 }
 
 pub type Size64 = BuildHasherDefault<Size64Hasher>;
@@ -421,10 +1174,394 @@ impl Hasher for Size64Hasher {
     }
 }
 
+#[cfg(test)]
+pub(crate) mod test_util {
+    use std::path::PathBuf;
+
+    use super::Database;
+    use crate::feed;
+
+    /// A temporary file, removed when dropped, even if the test fails
+    pub struct TempFile(pub PathBuf);
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// An empty database in a temporary file, `name` keeps those of
+    /// the tests apart
+    pub fn temp_database(name: &str) -> (TempFile, Database) {
+        let file = format!("rssbot-test-{}-{}.json", name, std::process::id());
+        let file = TempFile(std::env::temp_dir().join(file));
+        let db = Database::create(file.0.clone()).unwrap();
+        (file, db)
+    }
+
+    pub fn item(id: &str) -> feed::Item {
+        feed::Item {
+            id: Some(id.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    pub fn items(ids: &[&str]) -> Vec<feed::Item> {
+        ids.iter().map(|id| item(id)).collect()
+    }
+
+    pub fn rss(items: &[feed::Item]) -> feed::Rss {
+        feed::Rss {
+            title: "title".into(),
+            items: items.to_vec(),
+            ..Default::default()
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use super::test_util::{item, items, rss, temp_database};
     use super::*;
 
+    #[test]
+    fn feed_settings_cutoff() {
+        let mut settings = FeedSettings::default();
+        assert_eq!(settings.cutoff(), None);
+        settings.max_age = Some(0);
+        assert_eq!(settings.cutoff(), None);
+        settings.max_age = Some(1);
+        let by_age = settings.cutoff().unwrap();
+        assert!(by_age < Utc::now() - chrono::Duration::hours(23));
+        settings.subscribed_at = Some(SystemTime::now());
+        assert!(settings.cutoff().unwrap() > by_age);
+    }
+
+    #[test]
+    fn stable_hash() {
+        // SHA-256 of the empty string is e3b0c44298fc1c14...
+        assert_eq!(gen_hash(""), 0xe3b0_c442_98fc_1c14);
+    }
+
+    #[test]
+    fn migrate_legacy_hashes() {
+        let rss = |ids: &[&str]| rss(&items(ids));
+        let (_file, mut db) = temp_database("hashes");
+        db.subscribe(1, "https://example.com/a", &rss(&[]));
+        db.subscribe(1, "https://example.com/b", &rss(&[]));
+        for (link, legacy_keys) in &[
+            ("https://example.com/a", &["1", "2"][..]),
+            // made by another Rust release
+            ("https://example.com/b", &["x"][..]),
+        ] {
+            let feed = db.feeds.get_mut(&gen_hash(link)).unwrap();
+            feed.hash_list = legacy_keys.iter().map(|key| gen_legacy_hash(key)).collect();
+            feed.hash_version = 0;
+        }
+
+        let updates = db.update("https://example.com/a", rss(&["3", "2", "1"]));
+        assert!(matches!(&*updates, [FeedUpdate::Items(items)] if items == &[item("3")]));
+        let feed = db.get_feed("https://example.com/a").unwrap();
+        assert_eq!(feed.hash_version, HASH_VERSION);
+        assert_eq!(feed.hash_list[1..], [gen_hash("2"), gen_hash("1")]);
+
+        let updates = db.update("https://example.com/b", rss(&["1", "2"]));
+        assert!(updates.is_empty());
+        let updates = db.update("https://example.com/b", rss(&["3", "1", "2"]));
+        assert!(matches!(&*updates, [FeedUpdate::Items(items)] if items == &[item("3")]));
+
+        // pushed before the first fetch
+        db.subscribe(1, "https://example.com/c", &rss(&[]));
+        let feed = db
+            .feeds
+            .get_mut(&gen_hash("https://example.com/c"))
+            .unwrap();
+        feed.hash_list = vec![gen_legacy_hash("2"), gen_legacy_hash("1")];
+        feed.hash_version = 0;
+        let updates = db.update_pushed("https://example.com/c", rss(&["2"]));
+        assert!(updates.is_empty());
+        let updates = db.update_pushed("https://example.com/c", rss(&["3"]));
+        assert!(matches!(&*updates, [FeedUpdate::Items(items)] if items == &[item("3")]));
+        let feed = db.get_feed("https://example.com/c").unwrap();
+        assert_eq!(feed.hash_version, 0);
+        let updates = db.update("https://example.com/c", rss(&["3", "2"]));
+        assert!(updates.is_empty());
+        let feed = db.get_feed("https://example.com/c").unwrap();
+        assert_eq!(feed.hash_version, HASH_VERSION);
+        assert_eq!(feed.hash_list, [gen_hash("3"), gen_hash("2")]);
+    }
+
+    #[test]
+    fn dedup_keys() {
+        let item = feed::Item {
+            id: Some("id".into()),
+            title: Some("title".into()),
+            link: Some("https://example.com/a?utm_source=rss&p=1&fbclid=x".into()),
+            summary: Some("summary".into()),
+            ..Default::default()
+        };
+        let key = |key, strip_tracking| {
+            item_key(
+                &item,
+                &Dedup {
+                    key,
+                    strip_tracking,
+                },
+            )
+            .into_owned()
+        };
+        assert_eq!(key(DedupKey::Id, true), "id");
+        assert_eq!(key(DedupKey::Title, false), "title");
+        assert_eq!(key(DedupKey::Content, false), "summary");
+        assert_eq!(key(DedupKey::Link, true), "https://example.com/a?p=1");
+        assert_eq!(
+            key(DedupKey::TitleLink, false),
+            "titlehttps://example.com/a?utm_source=rss&p=1&fbclid=x"
+        );
+        assert_eq!(
+            strip_tracking_params("https://example.com/a?utm_medium=feed#top"),
+            "https://example.com/a#top"
+        );
+        assert_eq!(
+            strip_tracking_params("/relative?utm_source=x"),
+            "/relative?utm_source=x"
+        );
+
+        // the title and link are used without the field
+        let item = feed::Item {
+            title: Some("title".into()),
+            ..Default::default()
+        };
+        assert_eq!(item_key(&item, &Dedup::default()), "title");
+        assert_eq!(
+            "title_link".parse::<DedupKey>().unwrap(),
+            DedupKey::TitleLink
+        );
+        assert!("guid".parse::<DedupKey>().is_err());
+    }
+
+    #[test]
+    fn change_dedup() {
+        let item = |id, link: &str| feed::Item {
+            link: Some(link.to_owned()),
+            ..item(id)
+        };
+        let (_file, mut db) = temp_database("dedup");
+        let link = "https://example.com/feed";
+        db.subscribe(1, link, &rss(&[item("1", "/a"), item("2", "/b")]));
+        // a new item arrived before the change, it's still pushed after it
+        let current = [item("1", "/a"), item("2", "/b"), item("3", "/c")];
+        let dedup = Dedup {
+            key: DedupKey::Link,
+            strip_tracking: false,
+        };
+        assert!(db.set_dedup(link, dedup, &current));
+        assert_eq!(db.get_feed(link).unwrap().dedup, dedup);
+
+        // the ids are rotated
+        let updates = db.update(
+            link,
+            rss(&[item("4", "/a"), item("5", "/b"), item("6", "/c")]),
+        );
+        assert!(matches!(&*updates, [FeedUpdate::Items(items)] if items == &[item("6", "/c")]));
+    }
+
+    #[test]
+    fn item_edits() {
+        let item = |id, title: &str| feed::Item {
+            title: Some(title.to_owned()),
+            ..item(id)
+        };
+        let sent = |id| SentMessage {
+            chat: 1,
+            id,
+            caption: false,
+        };
+        let (_file, mut db) = temp_database("edits");
+        let link = "https://example.com/feed";
+        db.subscribe(1, link, &rss(&[item("1", "a")]));
+        let items = [item("3", "c"), item("2", "b"), item("1", "a")];
+        db.update(link, rss(&items));
+        db.record_message(link, &items[0], sent(30));
+        db.record_message(link, &items[1], sent(20));
+
+        let updates = db.update(
+            link,
+            rss(&[item("3", "c"), item("2", "b2"), item("1", "a")]),
+        );
+        assert!(matches!(
+            &*updates,
+            [FeedUpdate::ItemEdited(edited, messages)]
+                if edited == &item("2", "b2") && messages == &[sent(20)]
+        ));
+        // unchanged since the edit
+        let updates = db.update(
+            link,
+            rss(&[item("3", "c"), item("2", "b2"), item("1", "a")]),
+        );
+        assert!(updates.is_empty());
+
+        let updates = db.update(link, rss(&[item("3", "c"), item("1", "a")]));
+        assert!(
+            matches!(&*updates, [FeedUpdate::ItemRemoved(messages)] if messages == &[sent(20)])
+        );
+        // the older ones dropping out of the feed are not removed
+        let updates = db.update(link, rss(&[item("4", "d")]));
+        assert!(matches!(&*updates, [FeedUpdate::Items(_)]));
+    }
+
+    #[test]
+    fn partial_history() {
+        let rss = |ids: std::ops::Range<usize>| feed::Rss {
+            items: ids.map(|id| item(&id.to_string())).collect(),
+            warning: Some("broken".into()),
+            ..Default::default()
+        };
+        let (_file, mut db) = temp_database("partial");
+        let link = "https://example.com/broken";
+        db.subscribe(1, link, &rss(0..3));
+        for i in 3..10 {
+            assert_eq!(db.update(link, rss(i..i + 1)).len(), 1);
+        }
+        assert_eq!(db.get_feed(link).unwrap().hash_list.len(), 3);
+        db.update(link, rss(10..610));
+        db.update(link, rss(610..1210));
+        assert_eq!(db.get_feed(link).unwrap().hash_list.len(), MAX_HISTORY);
+        // pushes only have the changed items too
+        assert_eq!(db.update_pushed(link, rss(1210..1211)).len(), 1);
+        assert_eq!(db.get_feed(link).unwrap().hash_list.len(), MAX_HISTORY);
+    }
+
+    #[test]
+    fn request_options() {
+        assert_eq!(
+            "Basic user:pass:word".parse::<Auth>().unwrap(),
+            Auth::Basic {
+                username: "user".into(),
+                password: Some("pass:word".into()),
+            }
+        );
+        assert_eq!(
+            "bearer  abc=".parse::<Auth>().unwrap(),
+            Auth::Bearer("abc=".into())
+        );
+        assert!("bearer".parse::<Auth>().is_err());
+        assert!("digest a:b".parse::<Auth>().is_err());
+
+        let (_file, mut db) = temp_database("request");
+        let link = "https://example.com/private";
+        let options = RequestOptions {
+            auth: Some(Auth::Bearer("token".into())),
+            ..Default::default()
+        };
+        // set before subscribing, moved into the feed at subscription
+        db.set_request_options(1, link, options.clone());
+        assert_eq!(db.request_options(1, link), options);
+        assert_eq!(db.request_options(2, link), RequestOptions::default());
+        db.subscribe(1, link, &feed::Rss::default());
+        assert_eq!(db.get_feed(link).unwrap().request, options);
+        assert_eq!(db.request_options(1, link), options);
+        // only for subscribers
+        assert_eq!(db.request_options(2, link), RequestOptions::default());
+
+        // the options of a shared feed are never replaced by other chats
+        let other = RequestOptions {
+            proxy: Some("http://proxy.example.com".into()),
+            ..Default::default()
+        };
+        assert!(db.set_request_options(2, link, other.clone()));
+        db.subscribe(2, link, &feed::Rss::default());
+        assert_eq!(db.get_feed(link).unwrap().request, options);
+        assert!(!db.set_request_options(2, link, other.clone()));
+        assert!(!db.set_request_options(1, link, other));
+        assert_eq!(db.get_feed(link).unwrap().request, options);
+        // nor kept for them once their owner is gone
+        db.unsubscribe(1, link);
+        let feed = db.get_feed(link).unwrap();
+        assert_eq!(feed.request, RequestOptions::default());
+        assert_eq!(feed.request_owner, None);
+    }
+
+    #[test]
+    fn moved_feeds() {
+        let (_file, mut db) = temp_database("moved");
+        let (old, new) = ("http://example.com/rss", "https://example.com/feed.xml");
+        db.subscribe(1, old, &feed::Rss::default());
+        db.subscribe(2, old, &feed::Rss::default());
+        db.subscribe(2, new, &feed::Rss::default());
+        db.subscribe(3, new, &feed::Rss::default());
+
+        for _ in 0..REDIRECT_CONFIRMATIONS {
+            assert_eq!(db.track_redirect(old, Some(new)), None);
+        }
+        // not for long enough yet
+        let redirect = db.feeds.get_mut(&gen_hash(old)).unwrap().redirect.as_mut();
+        redirect.unwrap().since -= REDIRECT_CONFIRMATION_TIME;
+        assert_eq!(db.track_redirect(old, Some(new)).as_deref(), Some(new));
+        // starts over after a fetch without the redirect
+        assert_eq!(db.track_redirect(old, None), None);
+        assert_eq!(db.get_feed(old).unwrap().redirect, None);
+        // or to another target
+        let other = "https://example.org/feed.xml";
+        for _ in 0..REDIRECT_CONFIRMATIONS {
+            assert_eq!(db.track_redirect(old, Some(new)), None);
+        }
+        let redirect = db.feeds.get_mut(&gen_hash(old)).unwrap().redirect.as_mut();
+        redirect.unwrap().since -= REDIRECT_CONFIRMATION_TIME;
+        assert_eq!(db.track_redirect(old, Some(other)), None);
+        let redirect = db.get_feed(old).unwrap().redirect.unwrap();
+        assert_eq!((redirect.link.as_str(), redirect.count), (other, 1));
+        assert_eq!(db.track_redirect(old, None), None);
+
+        assert!(db.move_feed(old, new).is_some());
+        assert!(db.get_feed(old).is_none());
+        let feed = db.get_feed(new).unwrap();
+        assert_eq!(feed.subscribers.len(), 3);
+        for subscriber in 1..=3 {
+            assert!(db.is_subscribed(subscriber, new));
+            assert!(!db.is_subscribed(subscriber, old));
+            assert!(db.get_setting(subscriber, new).is_some());
+        }
+        assert_eq!(db.subscribed_feeds(2).unwrap().len(), 1);
+        assert!(db.move_feed(old, new).is_none());
+
+        // told once it's gone, again only after it's back
+        assert!(db.set_gone(new, true));
+        assert!(!db.set_gone(new, true));
+        assert!(db.set_gone(new, false));
+        assert!(db.set_gone(new, true));
+
+        // credentials are not sent to another host
+        let (private, moved) = ("https://example.com/private", "https://example.net/private");
+        let options = RequestOptions {
+            auth: Some(Auth::Bearer("token".into())),
+            ..Default::default()
+        };
+        db.set_request_options(4, private, options.clone());
+        db.subscribe(4, private, &feed::Rss::default());
+        assert_eq!(
+            db.move_feed(private, moved),
+            Some(MovedFeed {
+                options_dropped_for: Some(4)
+            })
+        );
+        let feed = db.get_feed(moved).unwrap();
+        assert_eq!(
+            (feed.request, feed.request_owner),
+            (RequestOptions::default(), None)
+        );
+        // but kept on the same one
+        db.set_request_options(4, moved, options.clone());
+        let same_host = "https://example.net/feed";
+        assert_eq!(
+            db.move_feed(moved, same_host),
+            Some(MovedFeed {
+                options_dropped_for: None
+            })
+        );
+        assert_eq!(db.get_feed(same_host).unwrap().request, options);
+    }
+
     #[test]
     fn size64hasher() {
         let mut h = Size64Hasher::default();
@@ -1,22 +1,28 @@
 use std::borrow::Cow;
 use std::cell::RefCell;
+use std::collections::HashSet;
 use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
 use std::str;
 
+use chrono::{DateTime, NaiveDate, Utc};
 use lazy_static::lazy_static;
 use quick_xml::events::attributes::Attributes;
-use quick_xml::events::BytesStart;
 use quick_xml::events::Event as XmlEvent;
+use quick_xml::events::{BytesStart, BytesText};
 use quick_xml::Reader as XmlReader;
 use regex::Regex;
-use serde::Deserialize;
+use url::Url;
+
+pub mod charset;
+pub mod json;
+pub mod validate;
 
 trait FromXml: Sized {
     fn from_xml<B: std::io::BufRead>(
         bufs: &BufPool,
-        reader: &mut XmlReader<B>,
+        reader: &mut NsReader<B>,
         start: &BytesStart,
     ) -> quick_xml::Result<Self>;
 }
@@ -26,19 +32,27 @@ enum AtomLink<'a> {
     Alternate(String),
     Source(String),
     Hub(String),
+    Enclosure(Attachment),
     Other(String, Cow<'a, str>),
 }
 
 fn parse_atom_link<'a, B: std::io::BufRead>(
-    reader: &mut XmlReader<B>,
+    reader: &XmlReader<B>,
     attributes: Attributes<'a>,
+    base: Option<&Url>,
 ) -> quick_xml::Result<Option<AtomLink<'a>>> {
+    let mut xml_base = None;
     let mut href = None;
     let mut rel = None;
+    let mut mime_type = None;
+    let mut length = None;
     for attribute in attributes {
         let attribute = attribute?;
         match &*reader.decode(attribute.key) {
-            "href" => href = Some(attribute.unescape_and_decode_value(reader)?),
+            "href" => href = Some(unescape(reader, &attribute.value)),
+            "xml:base" => xml_base = Some(unescape(reader, &attribute.value)),
+            "type" => mime_type = Some(unescape(reader, &attribute.value)),
+            "length" => length = unescape(reader, &attribute.value).trim().parse().ok(),
             "rel" => {
                 rel = Some(reader.decode(if let Cow::Borrowed(s) = attribute.value {
                     s
@@ -51,12 +65,21 @@ fn parse_atom_link<'a, B: std::io::BufRead>(
             _ => (),
         }
     }
+    let base = join_xml_base(base, xml_base.as_deref());
     Ok(href.map(move |href| {
+        let href = resolve_url(base.as_ref(), href);
         if let Some(rel) = rel {
             match &*rel {
                 "alternate" => AtomLink::Alternate(href),
                 "self" => AtomLink::Source(href),
                 "hub" => AtomLink::Hub(href),
+                "enclosure" => AtomLink::Enclosure(Attachment::new(
+                    href,
+                    mime_type,
+                    length,
+                    None,
+                    AttachmentKind::Other,
+                )),
                 _ => AtomLink::Other(href, rel),
             }
         } else {
@@ -65,22 +88,100 @@ fn parse_atom_link<'a, B: std::io::BufRead>(
     }))
 }
 
+/// Base URL of an element, `parent` is the base URL in scope
+fn xml_base<B: std::io::BufRead>(
+    reader: &XmlReader<B>,
+    element: &BytesStart,
+    parent: Option<&Url>,
+) -> quick_xml::Result<Option<Url>> {
+    let mut xml_base = None;
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        if reader.decode(attribute.key) == "xml:base" {
+            xml_base = Some(unescape(reader, &attribute.value));
+        }
+    }
+    Ok(join_xml_base(parent, xml_base.as_deref()))
+}
+
+fn join_xml_base(parent: Option<&Url>, xml_base: Option<&str>) -> Option<Url> {
+    let xml_base = match xml_base {
+        Some(xml_base) => xml_base.trim(),
+        None => return parent.cloned(),
+    };
+    let base = match parent {
+        Some(parent) => parent.join(xml_base),
+        None => Url::parse(xml_base),
+    };
+    base.ok().or_else(|| parent.cloned())
+}
+
+/// Resolve a relative link, absolute links are kept as is
+fn resolve_url(base: Option<&Url>, link: String) -> String {
+    match (base, Url::parse(link.trim())) {
+        (Some(base), Err(url::ParseError::RelativeUrlWithoutBase)) => base
+            .join(link.trim())
+            .map(|url| url.into_string())
+            .unwrap_or(link),
+        _ => link,
+    }
+}
+
+/// Unescape text or an attribute value like HTML does,
+/// undeclared entities and bare `&` are kept, control characters are dropped
+fn unescape<B: std::io::BufRead>(reader: &XmlReader<B>, escaped: &[u8]) -> String {
+    decode_html_entities(&reader.decode(escaped))
+        .chars()
+        .filter(|&c| !c.is_control() || c == '\t' || c == '\n' || c == '\r')
+        .collect()
+}
+
+/// Parse `enclosure`, `media:content` and `media:thumbnail`
+fn parse_attachment<B: std::io::BufRead>(
+    reader: &XmlReader<B>,
+    ns: Ns,
+    element: &BytesStart,
+) -> quick_xml::Result<Option<Attachment>> {
+    let fallback_kind = match (ns, &*reader.decode(element.local_name())) {
+        (Ns::Feed, "enclosure") | (Ns::Media, "content") => AttachmentKind::Other,
+        (Ns::Media, "thumbnail") => AttachmentKind::Image,
+        _ => return Ok(None),
+    };
+    let mut url = None;
+    let mut mime_type = None;
+    let mut length = None;
+    let mut medium = None;
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        match &*reader.decode(attribute.key) {
+            "url" => url = Some(unescape(reader, &attribute.value)),
+            "type" => mime_type = Some(unescape(reader, &attribute.value)),
+            "length" | "fileSize" => {
+                length = unescape(reader, &attribute.value).trim().parse().ok()
+            }
+            "medium" => medium = Some(unescape(reader, &attribute.value)),
+            _ => (),
+        }
+    }
+    Ok(url.map(|url| Attachment::new(url, mime_type, length, medium.as_deref(), fallback_kind)))
+}
+
 struct SkipThisElement;
 
 impl FromXml for SkipThisElement {
     fn from_xml<B: std::io::BufRead>(
         bufs: &BufPool,
-        reader: &mut XmlReader<B>,
+        reader: &mut NsReader<B>,
         _start: &BytesStart,
     ) -> quick_xml::Result<Self> {
         let mut buf = bufs.pop();
         let mut depth = 1u64;
         loop {
             match reader.read_event(&mut buf) {
-                Ok(XmlEvent::Start(_)) => depth += 1,
-                Ok(XmlEvent::End(_)) if depth == 1 => break,
-                Ok(XmlEvent::End(_)) => depth -= 1,
-                Ok(XmlEvent::Eof) => break, // just ignore EOF
+                Ok((_, XmlEvent::Start(_))) => depth += 1,
+                Ok((_, XmlEvent::End(_))) if depth == 1 => break,
+                Ok((_, XmlEvent::End(_))) => depth -= 1,
+                Ok((_, XmlEvent::Eof)) => break, // just ignore EOF
                 Err(err) => return Err(err.into()),
                 _ => (),
             }
@@ -93,21 +194,21 @@ impl FromXml for SkipThisElement {
 impl FromXml for Option<u32> {
     fn from_xml<B: std::io::BufRead>(
         bufs: &BufPool,
-        reader: &mut XmlReader<B>,
+        reader: &mut NsReader<B>,
         _start: &BytesStart,
     ) -> quick_xml::Result<Self> {
         let mut buf = bufs.pop();
         let mut output = None;
         loop {
             match reader.read_event(&mut buf) {
-                Ok(XmlEvent::Start(ref e)) => {
+                Ok((_, XmlEvent::Start(ref e))) => {
                     SkipThisElement::from_xml(bufs, reader, e)?;
                 }
-                Ok(XmlEvent::Text(ref e)) => {
+                Ok((_, XmlEvent::Text(ref e))) => {
                     let text = reader.decode(e);
                     output = text.parse().ok();
                 }
-                Ok(XmlEvent::End(_)) | Ok(XmlEvent::Eof) => break,
+                Ok((_, XmlEvent::End(_))) | Ok((_, XmlEvent::Eof)) => break,
                 Err(err) => return Err(err.into()),
                 _ => (),
             }
@@ -120,25 +221,25 @@ impl FromXml for Option<u32> {
 impl FromXml for Option<String> {
     fn from_xml<B: std::io::BufRead>(
         bufs: &BufPool,
-        reader: &mut XmlReader<B>,
+        reader: &mut NsReader<B>,
         _start: &BytesStart,
     ) -> quick_xml::Result<Self> {
         let mut buf = bufs.pop();
         let mut content: Option<String> = None;
         loop {
             match reader.read_event(&mut buf) {
-                Ok(XmlEvent::Start(ref e)) => {
+                Ok((_, XmlEvent::Start(ref e))) => {
                     SkipThisElement::from_xml(bufs, reader, e)?;
                 }
-                Ok(XmlEvent::Text(ref e)) => {
-                    let text = e.unescape_and_decode(reader)?;
+                Ok((_, XmlEvent::Text(ref e))) => {
+                    let text = unescape(reader, e);
                     content = Some(text);
                 }
-                Ok(XmlEvent::CData(ref e)) => {
+                Ok((_, XmlEvent::CData(ref e))) => {
                     let text = reader.decode(e).to_string();
                     content = Some(text);
                 }
-                Ok(XmlEvent::End(_)) | Ok(XmlEvent::Eof) => break,
+                Ok((_, XmlEvent::End(_))) | Ok((_, XmlEvent::Eof)) => break,
                 Err(err) => return Err(err.into()),
                 _ => (),
             }
@@ -148,23 +249,245 @@ impl FromXml for Option<String> {
     }
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+/// Atom text construct, `html` and `xhtml` content is kept as markup
+struct TextConstruct {
+    content: Option<String>,
+    is_markup: bool,
+}
+
+impl TextConstruct {
+    /// Plain text, for titles
+    fn into_text(self) -> Option<String> {
+        if self.is_markup {
+            self.content
+                .map(|html| html_to_text(&html))
+                .filter(|text| !text.is_empty())
+        } else {
+            self.content
+        }
+    }
+}
+
+impl FromXml for TextConstruct {
+    fn from_xml<B: std::io::BufRead>(
+        bufs: &BufPool,
+        reader: &mut NsReader<B>,
+        start: &BytesStart,
+    ) -> quick_xml::Result<Self> {
+        let mut content_type = None;
+        for attribute in start.attributes() {
+            let attribute = attribute?;
+            if reader.decode(attribute.key) == "type" {
+                content_type = Some(unescape(reader, &attribute.value));
+            }
+        }
+        // Atom 0.3 uses MIME types
+        let (content, is_markup) = match content_type.as_deref().map(str::trim) {
+            Some("xhtml") | Some("application/xhtml+xml") => {
+                // whitespaces between tags are a part of the text
+                reader.trim_text(false);
+                let markup = read_markup(bufs, reader);
+                reader.trim_text(true);
+                (markup?, true)
+            }
+            Some("html") | Some("text/html") => (
+                <Option<String> as FromXml>::from_xml(bufs, reader, start)?,
+                true,
+            ),
+            _ => (
+                <Option<String> as FromXml>::from_xml(bufs, reader, start)?,
+                false,
+            ),
+        };
+        Ok(TextConstruct { content, is_markup })
+    }
+}
+
+/// Markup inside the current element, prefixes of the tags are dropped
+fn read_markup<B: std::io::BufRead>(
+    bufs: &BufPool,
+    reader: &mut NsReader<B>,
+) -> quick_xml::Result<Option<String>> {
+    let mut buf = bufs.pop();
+    let mut markup = String::new();
+    let mut depth = 0u64;
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok((_, XmlEvent::Start(ref e))) => {
+                depth += 1;
+                markup.push_str(&format_tag(reader, e, ">"));
+            }
+            Ok((_, XmlEvent::Empty(ref e))) => {
+                markup.push_str(&format_tag(reader, e, "/>"));
+            }
+            Ok((_, XmlEvent::End(_))) if depth == 0 => break,
+            Ok((_, XmlEvent::End(ref e))) => {
+                depth -= 1;
+                markup.push_str("</");
+                markup.push_str(&reader.decode(e.local_name()));
+                markup.push('>');
+            }
+            Ok((_, XmlEvent::Text(ref e))) => markup.push_str(&reader.decode(e)),
+            Ok((_, XmlEvent::CData(ref e))) => {
+                markup.push_str(&reader.decode(BytesText::from_plain(e).escaped()))
+            }
+            Ok((_, XmlEvent::Eof)) => break,
+            Err(err) => return Err(err),
+            _ => (),
+        }
+        buf.clear();
+    }
+    let markup = markup.trim();
+    Ok(if markup.is_empty() {
+        None
+    } else {
+        Some(markup.to_owned())
+    })
+}
+
+fn format_tag<B: std::io::BufRead>(reader: &XmlReader<B>, tag: &BytesStart, end: &str) -> String {
+    // the raw tag is the name followed by the attributes
+    let attributes = &tag[tag.name().len()..];
+    format!(
+        "<{}{}{}",
+        reader.decode(tag.local_name()),
+        reader.decode(attributes),
+        end
+    )
+}
+
+impl FromXml for Option<DateTime<Utc>> {
+    fn from_xml<B: std::io::BufRead>(
+        bufs: &BufPool,
+        reader: &mut NsReader<B>,
+        start: &BytesStart,
+    ) -> quick_xml::Result<Self> {
+        let text = <Option<String> as FromXml>::from_xml(bufs, reader, start)?;
+        Ok(text.and_then(|s| parse_date(&s)))
+    }
+}
+
+/// RSS uses RFC 2822, Atom, `dc:date` and JSON Feed use RFC 3339 (or W3CDTF)
+fn parse_date(s: &str) -> Option<DateTime<Utc>> {
+    let s = s.trim();
+    DateTime::parse_from_rfc3339(s)
+        .or_else(|_| DateTime::parse_from_rfc2822(s))
+        .or_else(|e| match s.find(',') {
+            // the day of week is redundant, and it's often wrong
+            Some(i) => DateTime::parse_from_rfc2822(s[i + 1..].trim_start()),
+            None => Err(e),
+        })
+        .map(|date| date.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .map(|date| DateTime::from_utc(date.and_hms(0, 0, 0), Utc))
+        })
+}
+
+/// RSS `author` contains the name directly, Atom `author` has a `name` child
+struct AuthorName(Option<String>);
+
+impl FromXml for AuthorName {
+    fn from_xml<B: std::io::BufRead>(
+        bufs: &BufPool,
+        reader: &mut NsReader<B>,
+        _start: &BytesStart,
+    ) -> quick_xml::Result<Self> {
+        let mut buf = bufs.pop();
+        let mut text = None;
+        let mut name = None;
+        loop {
+            match reader.read_event(&mut buf) {
+                Ok((ns, XmlEvent::Start(ref e))) => {
+                    if ns == Ns::Feed && reader.decode(e.local_name()) == "name" {
+                        name = <Option<String> as FromXml>::from_xml(bufs, reader, e)?;
+                    } else {
+                        SkipThisElement::from_xml(bufs, reader, e)?;
+                    }
+                }
+                Ok((_, XmlEvent::Text(ref e))) => {
+                    text = Some(unescape(reader, e));
+                }
+                Ok((_, XmlEvent::CData(ref e))) => {
+                    text = Some(reader.decode(e).to_string());
+                }
+                Ok((_, XmlEvent::End(_))) | Ok((_, XmlEvent::Eof)) => break,
+                Err(err) => return Err(err.into()),
+                _ => (),
+            }
+            buf.clear();
+        }
+        Ok(AuthorName(name.or(text)))
+    }
+}
+
+/// Atom `category` uses the `term` attribute, RSS `category` uses the content
+fn parse_category_term<B: std::io::BufRead>(
+    reader: &XmlReader<B>,
+    element: &BytesStart,
+) -> quick_xml::Result<Option<String>> {
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        if reader.decode(attribute.key) == "term" {
+            return Ok(Some(unescape(reader, &attribute.value)));
+        }
+    }
+    Ok(None)
+}
+
+/// `media:group`
+impl FromXml for Vec<Attachment> {
+    fn from_xml<B: std::io::BufRead>(
+        bufs: &BufPool,
+        reader: &mut NsReader<B>,
+        _start: &BytesStart,
+    ) -> quick_xml::Result<Self> {
+        let mut buf = bufs.pop();
+        let mut attachments = Vec::new();
+        loop {
+            match reader.read_event(&mut buf) {
+                Ok((ns, XmlEvent::Empty(ref e))) => {
+                    attachments.extend(parse_attachment(reader, ns, e)?);
+                }
+                Ok((ns, XmlEvent::Start(ref e))) => {
+                    attachments.extend(parse_attachment(reader, ns, e)?);
+                    SkipThisElement::from_xml(bufs, reader, e)?;
+                }
+                Ok((_, XmlEvent::End(_))) | Ok((_, XmlEvent::Eof)) => break,
+                Err(err) => return Err(err.into()),
+                _ => (),
+            }
+            buf.clear();
+        }
+        Ok(attachments)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Rss {
     pub title: String,
-    #[serde(rename = "home_page_url", default)]
     pub link: String,
-    #[serde(rename = "feed_url")]
     pub source: Option<String>,
+    /// WebSub hub
+    pub hub: Option<String>,
     pub ttl: Option<u32>,
     pub items: Vec<Item>,
+    /// Error which stopped the parsing, the items before it are kept
+    pub warning: Option<String>,
 }
 
-impl FromXml for Rss {
+impl Rss {
     fn from_xml<B: std::io::BufRead>(
         bufs: &BufPool,
-        reader: &mut XmlReader<B>,
-        _start: &BytesStart,
+        reader: &mut NsReader<B>,
+        start: &BytesStart,
+        base: Option<&Url>,
+        max_items: Option<usize>,
     ) -> quick_xml::Result<Self> {
+        let base = xml_base(reader, start, base)?;
+        let base = base.as_ref();
         let mut buf = bufs.pop();
         let mut rss = Rss::default();
         let mut reading_rss_1_0_head = false;
@@ -173,71 +496,91 @@ impl FromXml for Rss {
         let mut sy_period: Option<SyPeriod> = None;
         let mut sy_freq: Option<u32> = None;
 
-        loop {
-            match reader.read_event(&mut buf) {
-                Ok(XmlEvent::Empty(ref e)) => {
-                    if reader.decode(e.local_name()) == "link" {
-                        match parse_atom_link(reader, e.attributes())? {
-                            Some(AtomLink::Alternate(link)) => rss.link = link,
-                            Some(AtomLink::Source(link)) => rss.source = Some(link),
-                            _ => {}
+        // a broken feed is read up to the error, nothing after it can be trusted
+        let mut read = || -> quick_xml::Result<()> {
+            loop {
+                match reader.read_event(&mut buf) {
+                    Ok((ns, XmlEvent::Empty(ref e))) => {
+                        if ns == Ns::Feed && reader.decode(e.local_name()) == "link" {
+                            match parse_atom_link(reader, e.attributes(), base)? {
+                                Some(AtomLink::Alternate(link)) => rss.link = link,
+                                Some(AtomLink::Source(link)) => rss.source = Some(link),
+                                Some(AtomLink::Hub(link)) => rss.hub = Some(link),
+                                _ => {}
+                            }
                         }
                     }
-                }
-                Ok(XmlEvent::Start(ref e)) => {
-                    match &*reader.decode(e.local_name()) {
-                        "channel" => {
-                            // RSS 0.9 1.0
-                            reading_rss_1_0_head = true;
-                        }
-                        "title" => {
-                            if let Some(title) =
-                                <Option<String> as FromXml>::from_xml(bufs, reader, e)?
-                            {
-                                rss.title = title;
+                    Ok((ns, XmlEvent::Start(ref e))) => {
+                        match (ns, &*reader.decode(e.local_name())) {
+                            (Ns::Feed, "channel") => {
+                                // RSS 0.9 1.0
+                                reading_rss_1_0_head = true;
                             }
-                        }
-                        "link" => {
-                            if let Some(link) =
-                                <Option<String> as FromXml>::from_xml(bufs, reader, e)?
-                            {
-                                // RSS
-                                rss.link = link;
-                            } else {
-                                // ATOM
-                                match parse_atom_link(reader, e.attributes())? {
-                                    Some(AtomLink::Alternate(link)) => rss.link = link,
-                                    Some(AtomLink::Source(link)) => rss.source = Some(link),
-                                    _ => {}
+                            (Ns::Feed, "title") => {
+                                if let Some(title) =
+                                    TextConstruct::from_xml(bufs, reader, e)?.into_text()
+                                {
+                                    rss.title = title;
                                 }
                             }
-                        }
-                        "item" | "entry" => {
-                            rss.items.push(Item::from_xml(bufs, reader, e)?);
-                        }
-                        "ttl" => {
-                            rss.ttl = <Option<u32> as FromXml>::from_xml(bufs, reader, e)?;
-                        }
-                        "updatePeriod" => {
-                            sy_period = <Option<SyPeriod> as FromXml>::from_xml(bufs, reader, e)?;
-                        }
-                        "updateFrequency" => {
-                            sy_freq = <Option<u32> as FromXml>::from_xml(bufs, reader, e)?;
-                        }
-                        _ => {
-                            SkipThisElement::from_xml(bufs, reader, e)?;
+                            (Ns::Feed, "link") => {
+                                if let Some(link) =
+                                    <Option<String> as FromXml>::from_xml(bufs, reader, e)?
+                                {
+                                    // RSS
+                                    rss.link = resolve_url(base, link);
+                                } else {
+                                    // ATOM
+                                    match parse_atom_link(reader, e.attributes(), base)? {
+                                        Some(AtomLink::Alternate(link)) => rss.link = link,
+                                        Some(AtomLink::Source(link)) => rss.source = Some(link),
+                                        Some(AtomLink::Hub(link)) => rss.hub = Some(link),
+                                        _ => {}
+                                    }
+                                }
+                            }
+                            (Ns::Feed, "item") | (Ns::Feed, "entry") => {
+                                rss.items.push(Item::from_xml(bufs, reader, e, base)?);
+                                // the rest of the feed is not read at all
+                                if Some(rss.items.len()) == max_items {
+                                    break;
+                                }
+                            }
+                            (Ns::Feed, "ttl") => {
+                                rss.ttl = <Option<u32> as FromXml>::from_xml(bufs, reader, e)?;
+                            }
+                            (Ns::Sy, "updatePeriod") => {
+                                sy_period =
+                                    <Option<SyPeriod> as FromXml>::from_xml(bufs, reader, e)?;
+                            }
+                            (Ns::Sy, "updateFrequency") => {
+                                sy_freq = <Option<u32> as FromXml>::from_xml(bufs, reader, e)?;
+                            }
+                            _ => {
+                                SkipThisElement::from_xml(bufs, reader, e)?;
+                            }
                         }
                     }
+                    Ok((_, XmlEvent::End(_))) if reading_rss_1_0_head => {
+                        // reader.decode(e.local_name())? == "channel";
+                        reading_rss_1_0_head = false;
+                    }
+                    Ok((_, XmlEvent::End(_))) => break,
+                    Ok((_, XmlEvent::Eof)) => {
+                        return Err(quick_xml::Error::UnexpectedEof("feed".to_string()))
+                    }
+                    Err(err) => return Err(err),
+                    _ => (),
                 }
-                Ok(XmlEvent::End(_)) if reading_rss_1_0_head => {
-                    // reader.decode(e.local_name())? == "channel";
-                    reading_rss_1_0_head = false;
-                }
-                Ok(XmlEvent::End(_)) | Ok(XmlEvent::Eof) => break,
-                Err(err) => return Err(err.into()),
-                _ => (),
+                buf.clear();
             }
-            buf.clear();
+            Ok(())
+        };
+        if let Err(err) = read() {
+            if rss.title.is_empty() && rss.items.is_empty() {
+                return Err(err);
+            }
+            rss.warning = Some(err.to_string());
         }
         if rss.ttl.is_none() {
             let freq = sy_freq.unwrap_or(1); // 1 is the default value
@@ -254,65 +597,230 @@ impl FromXml for Rss {
     }
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Item {
     pub title: Option<String>,
-    #[serde(rename = "url")]
     pub link: Option<String>,
     pub id: Option<String>,
+    /// RSS `description`, Atom `summary`
+    pub summary: Option<String>,
+    /// RSS `content:encoded`, Atom `content`
+    pub content: Option<String>,
+    /// Publication date, falls back to the last updated date
+    pub published: Option<DateTime<Utc>>,
+    pub attachments: Vec<Attachment>,
+    pub authors: Vec<String>,
+    pub categories: Vec<String>,
+}
+
+impl Item {
+    /// Plain text excerpt of the item body, at most `max_chars` characters
+    pub fn excerpt(&self, max_chars: usize) -> Option<String> {
+        let body = self.summary.as_ref().or_else(|| self.content.as_ref())?;
+        let text = html_to_text(body);
+        if text.is_empty() {
+            return None;
+        }
+        if text.chars().count() <= max_chars {
+            return Some(text);
+        }
+        let mut excerpt: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+        excerpt.truncate(excerpt.trim_end().len());
+        excerpt.push('…');
+        Some(excerpt)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub url: String,
+    pub mime_type: Option<String>,
+    pub length: Option<u64>,
+    pub kind: AttachmentKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachmentKind {
+    Image,
+    Audio,
+    Video,
+    Other,
 }
 
-impl FromXml for Item {
+impl Attachment {
+    /// The kind is decided by MIME type, `media:content` medium or file extension,
+    /// in that order
+    fn new(
+        url: String,
+        mime_type: Option<String>,
+        length: Option<u64>,
+        medium: Option<&str>,
+        fallback_kind: AttachmentKind,
+    ) -> Self {
+        let by_mime_type = mime_type
+            .as_ref()
+            .and_then(|t| t.split('/').next())
+            .and_then(AttachmentKind::from_name);
+        let by_extension = || {
+            let path = url
+                .split(|c| c == '?' || c == '#')
+                .next()
+                .unwrap_or_default();
+            let file_name = path.rsplit('/').next().unwrap_or_default();
+            let extension = &file_name[file_name.rfind('.')? + 1..];
+            match &*extension.to_ascii_lowercase() {
+                "jpg" | "jpeg" | "png" | "gif" | "webp" => Some(AttachmentKind::Image),
+                "mp3" | "m4a" | "ogg" | "oga" | "opus" | "aac" | "flac" | "wav" => {
+                    Some(AttachmentKind::Audio)
+                }
+                "mp4" | "m4v" | "webm" | "mov" => Some(AttachmentKind::Video),
+                _ => None,
+            }
+        };
+        let kind = by_mime_type
+            .or_else(|| medium.and_then(AttachmentKind::from_name))
+            .or_else(by_extension)
+            .unwrap_or(fallback_kind);
+        Attachment {
+            url,
+            mime_type,
+            length,
+            kind,
+        }
+    }
+}
+
+impl AttachmentKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "image" => Some(AttachmentKind::Image),
+            "audio" => Some(AttachmentKind::Audio),
+            "video" => Some(AttachmentKind::Video),
+            _ => None,
+        }
+    }
+}
+
+impl Item {
     fn from_xml<B: std::io::BufRead>(
         bufs: &BufPool,
-        reader: &mut XmlReader<B>,
-        _start: &BytesStart,
+        reader: &mut NsReader<B>,
+        start: &BytesStart,
+        base: Option<&Url>,
     ) -> quick_xml::Result<Self> {
+        let base = xml_base(reader, start, base)?;
+        let base = base.as_ref();
         let mut buf = bufs.pop();
         let mut item = Item::default();
+        let mut updated = None;
         loop {
             match reader.read_event(&mut buf) {
-                Ok(XmlEvent::Empty(ref e)) => {
-                    if reader.decode(e.name()) == "link" {
-                        if let Some(AtomLink::Alternate(link)) =
-                            parse_atom_link(reader, e.attributes())?
-                        {
-                            item.link = Some(link);
+                Ok((ns, XmlEvent::Empty(ref e))) => {
+                    let name = reader.decode(e.local_name());
+                    if ns == Ns::Feed && name == "link" {
+                        match parse_atom_link(reader, e.attributes(), base)? {
+                            Some(AtomLink::Alternate(link)) => item.link = Some(link),
+                            Some(AtomLink::Enclosure(attachment)) => {
+                                item.attachments.push(attachment)
+                            }
+                            _ => {}
                         }
+                    } else if ns == Ns::Feed && name == "category" {
+                        item.categories.extend(parse_category_term(reader, e)?);
+                    } else {
+                        item.attachments.extend(parse_attachment(reader, ns, e)?);
                     }
                 }
-                Ok(XmlEvent::Start(ref e)) => {
-                    match &*reader.decode(e.name()) {
-                        "title" => {
-                            item.title = <Option<String> as FromXml>::from_xml(bufs, reader, e)?;
+                Ok((ns, XmlEvent::Start(ref e))) => {
+                    match (ns, &*reader.decode(e.local_name())) {
+                        (Ns::Feed, "title") => {
+                            item.title = TextConstruct::from_xml(bufs, reader, e)?.into_text();
                         }
-                        "link" => {
+                        (Ns::Feed, "link") => {
                             if let Some(link) =
                                 <Option<String> as FromXml>::from_xml(bufs, reader, e)?
                             {
                                 // RSS
-                                item.link = Some(link);
-                            } else if let Some(AtomLink::Alternate(link)) =
-                                parse_atom_link(reader, e.attributes())?
-                            {
+                                item.link = Some(resolve_url(base, link));
+                            } else {
                                 // ATOM
-                                item.link = Some(link);
+                                match parse_atom_link(reader, e.attributes(), base)? {
+                                    Some(AtomLink::Alternate(link)) => item.link = Some(link),
+                                    Some(AtomLink::Enclosure(attachment)) => {
+                                        item.attachments.push(attachment)
+                                    }
+                                    _ => {}
+                                }
                             }
                         }
-                        "id" | "guid" => {
+                        (Ns::Feed, "enclosure")
+                        | (Ns::Media, "content")
+                        | (Ns::Media, "thumbnail") => {
+                            item.attachments.extend(parse_attachment(reader, ns, e)?);
+                            SkipThisElement::from_xml(bufs, reader, e)?;
+                        }
+                        (Ns::Feed, "author") | (Ns::Dc, "creator") => {
+                            let AuthorName(name) = AuthorName::from_xml(bufs, reader, e)?;
+                            item.authors.extend(name);
+                        }
+                        (Ns::Feed, "category") | (Ns::Dc, "subject") => {
+                            let term = parse_category_term(reader, e)?;
+                            let text = <Option<String> as FromXml>::from_xml(bufs, reader, e)?;
+                            item.categories.extend(text.or(term));
+                        }
+                        (Ns::Media, "group") => {
+                            let attachments =
+                                <Vec<Attachment> as FromXml>::from_xml(bufs, reader, e)?;
+                            item.attachments.extend(attachments);
+                        }
+                        (Ns::Feed, "id") | (Ns::Feed, "guid") => {
                             item.id = <Option<String> as FromXml>::from_xml(bufs, reader, e)?;
                         }
+                        (Ns::Feed, "description") | (Ns::Feed, "summary") => {
+                            item.summary = TextConstruct::from_xml(bufs, reader, e)?.content;
+                        }
+                        (Ns::Content, "encoded") | (Ns::Feed, "content") => {
+                            let content = TextConstruct::from_xml(bufs, reader, e)?.content;
+                            // Atom 0.3 allows multiple content, the first one is preferred
+                            if item.content.is_none() {
+                                item.content = content;
+                            }
+                        }
+                        (Ns::Feed, "pubDate")
+                        | (Ns::Dc, "date")
+                        | (Ns::Feed, "published")
+                        | (Ns::Feed, "issued") => {
+                            item.published =
+                                <Option<DateTime<Utc>> as FromXml>::from_xml(bufs, reader, e)?;
+                        }
+                        (Ns::Feed, "updated") | (Ns::Feed, "modified") => {
+                            updated =
+                                <Option<DateTime<Utc>> as FromXml>::from_xml(bufs, reader, e)?;
+                        }
                         _ => {
                             SkipThisElement::from_xml(bufs, reader, e)?;
                         }
                     }
                 }
-                Ok(XmlEvent::End(_)) | Ok(XmlEvent::Eof) => break,
-                Err(err) => return Err(err.into()),
+                Ok((_, XmlEvent::End(_))) => break,
+                Ok((_, XmlEvent::Eof)) => {
+                    return Err(quick_xml::Error::UnexpectedEof("item".to_string()))
+                }
+                Err(err) => return Err(err),
                 _ => (),
             }
             buf.clear();
         }
+        if item.published.is_none() {
+            item.published = updated;
+        }
+        // the same file may appear in both `enclosure` and `media:content`
+        let mut urls = HashSet::new();
+        item.attachments.retain(|a| urls.insert(a.url.clone()));
+        for list in &mut [&mut item.authors, &mut item.categories] {
+            let mut seen = HashSet::new();
+            list.retain(|s| !s.trim().is_empty() && seen.insert(s.clone()));
+        }
         Ok(item)
     }
 }
@@ -329,17 +837,17 @@ enum SyPeriod {
 impl FromXml for Option<SyPeriod> {
     fn from_xml<B: std::io::BufRead>(
         bufs: &BufPool,
-        reader: &mut XmlReader<B>,
+        reader: &mut NsReader<B>,
         _start: &BytesStart,
     ) -> quick_xml::Result<Self> {
         let mut buf = bufs.pop();
         let mut output = None;
         loop {
             match reader.read_event(&mut buf) {
-                Ok(XmlEvent::Start(ref e)) => {
+                Ok((_, XmlEvent::Start(ref e))) => {
                     SkipThisElement::from_xml(bufs, reader, e)?;
                 }
-                Ok(XmlEvent::Text(ref e)) => {
+                Ok((_, XmlEvent::Text(ref e))) => {
                     let period = match &*reader.decode(e) {
                         "hourly" => SyPeriod::Hourly,
                         "daily" => SyPeriod::Daily,
@@ -350,7 +858,7 @@ impl FromXml for Option<SyPeriod> {
                     };
                     output = Some(period);
                 }
-                Ok(XmlEvent::End(_)) | Ok(XmlEvent::Eof) => break,
+                Ok((_, XmlEvent::End(_))) | Ok((_, XmlEvent::Eof)) => break,
                 Err(err) => return Err(err.into()),
                 _ => (),
             }
@@ -361,23 +869,30 @@ impl FromXml for Option<SyPeriod> {
 }
 
 /// NOTE: This function doesn't check the syntax of feed, it only cares about performance
-pub fn parse<B: std::io::BufRead>(reader: B) -> quick_xml::Result<Rss> {
-    let mut reader = XmlReader::from_reader(reader);
-    reader.trim_text(true);
+/// Links are resolved against `xml:base`, which is relative to `base`,
+/// parsing stops after `max_items` items
+pub fn parse<B: std::io::BufRead>(
+    reader: B,
+    base: Option<&Url>,
+    max_items: Option<usize>,
+) -> quick_xml::Result<Rss> {
+    let mut reader = NsReader::from_reader(reader);
     let bufs = BufPool::new(4, 512);
     let mut buf = bufs.pop();
     loop {
-        match dbg!(reader.read_event(&mut buf)) {
-            Ok(XmlEvent::Start(ref e)) => match &*reader.decode(e.name()) {
-                "rss" => continue,
-                "channel" | "feed" | "rdf:RDF" => {
-                    return Rss::from_xml(&bufs, &mut reader, e);
+        match reader.read_event(&mut buf) {
+            Ok((ns, XmlEvent::Start(ref e))) => match (ns, &*reader.decode(e.local_name())) {
+                (Ns::Feed, "rss") => continue,
+                (Ns::Feed, "channel") | (Ns::Feed, "feed") | (Ns::Rdf, "RDF") => {
+                    return Rss::from_xml(&bufs, &mut reader, e, base, max_items);
                 }
                 _ => {
                     SkipThisElement::from_xml(&bufs, &mut reader, e)?;
                 }
             },
-            Ok(XmlEvent::Eof) => return Err(quick_xml::Error::UnexpectedEof("feed".to_string())),
+            Ok((_, XmlEvent::Eof)) => {
+                return Err(quick_xml::Error::UnexpectedEof("feed".to_string()))
+            }
             Err(err) => return Err(err.into()),
             _ => (),
         }
@@ -385,42 +900,180 @@ pub fn parse<B: std::io::BufRead>(reader: B) -> quick_xml::Result<Rss> {
     }
 }
 
-fn url_relative_to_absolute(link: &mut String, host: &str) {
-    match link.as_str() {
-        _ if link.starts_with("//") => {
-            let mut s = String::from("http:");
-            s.push_str(link);
-            *link = s;
-        }
-        _ if link.starts_with('/') => {
-            let mut s = String::from(host);
-            s.push_str(link);
-            *link = s;
-        }
-        _ => (),
+/// Convert a HTML fragment into plain text,
+/// tags are stripped, entities are decoded and whitespaces are collapsed
+pub fn html_to_text(html: &str) -> String {
+    lazy_static! {
+        static ref TAG: Regex = Regex::new(
+            r"(?is)<!--.*?-->|<script\b.*?</script\s*>|<style\b.*?</style\s*>|</?([a-z0-9]*)[^>]*>"
+        )
+        .unwrap();
     }
+    let stripped = TAG.replace_all(html, |caps: &regex::Captures| {
+        let name = caps.get(1).map(|m| m.as_str()).unwrap_or_default();
+        match &*name.to_ascii_lowercase() {
+            "br" | "p" | "div" | "li" | "tr" | "td" | "th" | "blockquote" | "pre" | "hr" | "h1"
+            | "h2" | "h3" | "h4" | "h5" | "h6" => " ",
+            _ => "",
+        }
+    });
+    let decoded = decode_html_entities(&stripped);
+    decoded.split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
-pub fn fix_relative_url(mut rss: Rss, rss_link: &str) -> Rss {
+pub fn decode_html_entities(s: &str) -> Cow<str> {
     lazy_static! {
-        static ref HOST: Regex = Regex::new(r"^(https?://[^/]+)").unwrap();
-    }
-    let rss_host = HOST
-        .captures(rss_link)
-        .map_or(rss_link, |r| r.get(0).unwrap().as_str());
-    match rss.link.as_str() {
-        "" | "/" => rss.link = rss_host.to_owned(),
-        _ => url_relative_to_absolute(&mut rss.link, rss_host),
+        static ref ENTITY: Regex =
+            Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+[0-9]*);").unwrap();
     }
+    ENTITY.replace_all(s, |caps: &regex::Captures| {
+        let entity = &caps[1];
+        let c = if entity.starts_with("#x") || entity.starts_with("#X") {
+            u32::from_str_radix(&entity[2..], 16)
+                .ok()
+                .and_then(std::char::from_u32)
+        } else if entity.starts_with('#') {
+            entity[1..].parse().ok().and_then(std::char::from_u32)
+        } else {
+            match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                "hellip" => Some('…'),
+                "mdash" => Some('—'),
+                "ndash" => Some('–'),
+                "lsquo" => Some('‘'),
+                "rsquo" => Some('’'),
+                "ldquo" => Some('“'),
+                "rdquo" => Some('”'),
+                "laquo" => Some('«'),
+                "raquo" => Some('»'),
+                "middot" => Some('·'),
+                "copy" => Some('©'),
+                "reg" => Some('®'),
+                "trade" => Some('™'),
+                _ => None,
+            }
+        };
+        c.map(|c| c.to_string())
+            .unwrap_or_else(|| caps[0].to_owned())
+    })
+}
+
+/// Resolve relative links against the URL the feed was fetched from
+pub fn resolve_relative_urls(mut rss: Rss, base: &Url) -> Rss {
+    rss.link = match rss.link.trim() {
+        "" | "/" => base.origin().ascii_serialization(),
+        _ => resolve_url(Some(base), rss.link),
+    };
+    rss.source = rss.source.map(|link| resolve_url(Some(base), link));
+    rss.hub = rss.hub.map(|link| resolve_url(Some(base), link));
     for item in &mut rss.items {
-        if let Some(link) = item.link.as_mut() {
-            url_relative_to_absolute(link, rss_host);
+        item.link = item.link.take().map(|link| resolve_url(Some(base), link));
+        for attachment in &mut item.attachments {
+            let url = mem::replace(&mut attachment.url, String::new());
+            attachment.url = resolve_url(Some(base), url);
         }
     }
 
     rss
 }
 
+/// Namespaces of the elements the parser understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ns {
+    /// RSS 0.9 to 2.0 and Atom, feeds mix their elements freely
+    Feed,
+    Rdf,
+    Content,
+    Dc,
+    Media,
+    Sy,
+    Other,
+}
+
+impl Ns {
+    fn of_element(uri: Option<&[u8]>, qname: &[u8]) -> Self {
+        match uri {
+            None => match qname.iter().position(|&b| b == b':') {
+                // the prefix is not declared, guess by the usual one
+                Some(i) => Ns::of_prefix(&qname[..i]),
+                None => Ns::Feed,
+            },
+            Some(b"http://my.netscape.com/rdf/simple/0.9/")
+            | Some(b"http://purl.org/rss/1.0/")
+            | Some(b"http://backend.userland.com/rss2")
+            | Some(b"http://www.w3.org/2005/Atom")
+            | Some(b"http://purl.org/atom/ns#") => Ns::Feed,
+            Some(b"http://www.w3.org/1999/02/22-rdf-syntax-ns#") => Ns::Rdf,
+            Some(b"http://purl.org/rss/1.0/modules/content/") => Ns::Content,
+            Some(b"http://purl.org/dc/elements/1.1/") | Some(b"http://purl.org/dc/terms/") => {
+                Ns::Dc
+            }
+            Some(b"http://search.yahoo.com/mrss/") | Some(b"http://search.yahoo.com/mrss") => {
+                Ns::Media
+            }
+            Some(b"http://purl.org/rss/1.0/modules/syndication/") => Ns::Sy,
+            Some(_) => Ns::Other,
+        }
+    }
+
+    fn of_prefix(prefix: &[u8]) -> Self {
+        match prefix {
+            b"atom" => Ns::Feed,
+            b"rdf" => Ns::Rdf,
+            b"content" => Ns::Content,
+            b"dc" | b"dcterms" => Ns::Dc,
+            b"media" => Ns::Media,
+            b"sy" => Ns::Sy,
+            _ => Ns::Other,
+        }
+    }
+}
+
+/// XML reader which resolves the namespace of every element
+struct NsReader<B: std::io::BufRead> {
+    reader: XmlReader<B>,
+    ns_buf: Vec<u8>,
+}
+
+impl<B: std::io::BufRead> NsReader<B> {
+    fn from_reader(reader: B) -> Self {
+        let mut reader = XmlReader::from_reader(reader);
+        reader.trim_text(true);
+        NsReader {
+            reader,
+            ns_buf: Vec::new(),
+        }
+    }
+
+    /// The namespace is only resolved for `Start` and `Empty` events
+    fn read_event<'b>(&mut self, buf: &'b mut Vec<u8>) -> quick_xml::Result<(Ns, XmlEvent<'b>)> {
+        let (uri, event) = self.reader.read_namespaced_event(buf, &mut self.ns_buf)?;
+        let ns = match &event {
+            XmlEvent::Start(e) | XmlEvent::Empty(e) => Ns::of_element(uri, e.name()),
+            _ => Ns::Other,
+        };
+        Ok((ns, event))
+    }
+}
+
+impl<B: std::io::BufRead> Deref for NsReader<B> {
+    type Target = XmlReader<B>;
+    fn deref(&self) -> &Self::Target {
+        &self.reader
+    }
+}
+
+impl<B: std::io::BufRead> DerefMut for NsReader<B> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.reader
+    }
+}
+
 struct BufPool {
     pool: Rc<RefCell<Vec<Vec<u8>>>>,
     capacity: usize,
@@ -476,19 +1129,30 @@ impl DerefMut for Buffer {
 mod test {
     use std::io::Cursor;
 
+    use chrono::TimeZone;
+
     use super::*;
 
+    fn enclosure(prefix: &str) -> Attachment {
+        Attachment {
+            url: format!("{}^url", prefix),
+            mime_type: Some(format!("{}^type", prefix)),
+            length: Some(100),
+            kind: AttachmentKind::Other,
+        }
+    }
+
     #[test]
     fn encoding() {
         let s: &[u8] = &*include_bytes!("../tests/data/encoding.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None, None).unwrap();
         assert_eq!(r.title, "虎扑足球新闻")
     }
 
     #[test]
     fn atom03() {
         let s = include_str!("../tests/data/atom_0.3.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None, None).unwrap();
         assert_eq!(
             r,
             Rss {
@@ -499,11 +1163,23 @@ mod test {
                         title: Some("atom_0.3.feed.entry[0].title".into()),
                         link: Some("atom_0.3.feed.entry[0].link^href".into()),
                         id: Some("atom_0.3.feed.entry[0]^id".into()),
+                        summary: Some("atom_0.3.feed.entry[0].summary".into()),
+                        content: Some("atom_0.3.feed.entry[0].content[0]".into()),
+                        published: Some(Utc.ymd(2000, 1, 1).and_hms(1, 0, 0)),
+                        attachments: vec![],
+                        authors: vec!["atom_0.3.feed.entry[0].author.name".into()],
+                        categories: vec![],
                     },
                     Item {
                         title: Some("atom_0.3.feed.entry[1].title".into()),
                         link: Some("atom_0.3.feed.entry[1].link^href".into()),
                         id: Some("atom_0.3.feed.entry[1]^id".into()),
+                        summary: Some("atom_0.3.feed.entry[1].summary".into()),
+                        content: Some("atom_0.3.feed.entry[1].content[0]".into()),
+                        published: Some(Utc.ymd(2000, 2, 1).and_hms(1, 0, 0)),
+                        attachments: vec![],
+                        authors: vec!["atom_0.3.feed.entry[1].author.name".into()],
+                        categories: vec![],
                     },
                 ],
                 ..Rss::default()
@@ -514,7 +1190,7 @@ mod test {
     #[test]
     fn atom10() {
         let s = include_str!("../tests/data/atom_1.0.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None, None).unwrap();
         assert_eq!(
             r,
             Rss {
@@ -526,11 +1202,33 @@ mod test {
                         title: Some("atom_1.0.feed.entry[0].title".into()),
                         link: Some("http://example.com/blog/entry1_plain".into()),
                         id: Some("atom_1.0.feed.entry[0]^id".into()),
+                        summary: Some("atom_1.0.feed.entry[0].summary".into()),
+                        content: Some("atom_1.0.feed.entry[0].content[0]".into()),
+                        published: Some(Utc.ymd(2000, 1, 1).and_hms(1, 0, 0)),
+                        attachments: vec![Attachment {
+                            url: "http://example.com/blog/enclosure1.gif".into(),
+                            mime_type: Some("image/gif".into()),
+                            length: None,
+                            kind: AttachmentKind::Image,
+                        }],
+                        authors: vec!["atom_1.0.feed.entry[0].author.name".into()],
+                        categories: vec![],
                     },
                     Item {
                         title: Some("atom_1.0.feed.entry[1].title".into()),
                         link: Some("http://example.com/blog/entry2".into()),
                         id: Some("atom_1.0.feed.entry[1]^id".into()),
+                        summary: Some("atom_1.0.feed.entry[1].summary".into()),
+                        content: Some("atom_1.0.feed.entry[1].content[0]".into()),
+                        published: Some(Utc.ymd(2000, 2, 1).and_hms(1, 0, 0)),
+                        attachments: vec![Attachment {
+                            url: "http://example.com/blog/enclosure2.gif".into(),
+                            mime_type: Some("image/gif".into()),
+                            length: None,
+                            kind: AttachmentKind::Image,
+                        }],
+                        authors: vec!["atom_1.0.feed.entry[1].author.name".into()],
+                        categories: vec![],
                     },
                 ],
                 ..Rss::default()
@@ -541,7 +1239,7 @@ mod test {
     #[test]
     fn rss09() {
         let s = include_str!("../tests/data/rss_0.9.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None, None).unwrap();
         assert_eq!(
             r,
             Rss {
@@ -552,11 +1250,23 @@ mod test {
                         title: Some("rss_0.9.item[0].title".into()),
                         link: Some("rss_0.9.item[0].link".into()),
                         id: None,
+                        summary: None,
+                        content: None,
+                        published: None,
+                        attachments: vec![],
+                        authors: vec![],
+                        categories: vec![],
                     },
                     Item {
                         title: Some("rss_0.9.item[1].title".into()),
                         link: Some("rss_0.9.item[1].link".into()),
                         id: None,
+                        summary: None,
+                        content: None,
+                        published: None,
+                        attachments: vec![],
+                        authors: vec![],
+                        categories: vec![],
                     },
                 ],
                 ..Rss::default()
@@ -567,7 +1277,7 @@ mod test {
     #[test]
     fn rss091() {
         let s = include_str!("../tests/data/rss_0.91.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None, None).unwrap();
         assert_eq!(
             r,
             Rss {
@@ -578,11 +1288,23 @@ mod test {
                         title: Some("rss_0.91.channel.item[0].title".into()),
                         link: Some("rss_0.91.channel.item[0].link".into()),
                         id: None,
+                        summary: Some("rss_0.91.channel.item[0].description".into()),
+                        content: None,
+                        published: None,
+                        attachments: vec![],
+                        authors: vec![],
+                        categories: vec![],
                     },
                     Item {
                         title: Some("rss_0.91.channel.item[1].title".into()),
                         link: Some("rss_0.91.channel.item[1].link".into()),
                         id: None,
+                        summary: Some("rss_0.91.channel.item[1].description".into()),
+                        content: None,
+                        published: None,
+                        attachments: vec![],
+                        authors: vec![],
+                        categories: vec![],
                     },
                 ],
                 ..Rss::default()
@@ -593,7 +1315,7 @@ mod test {
     #[test]
     fn rss092() {
         let s = include_str!("../tests/data/rss_0.92.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None, None).unwrap();
         assert_eq!(
             r,
             Rss {
@@ -604,11 +1326,29 @@ mod test {
                         title: Some("rss_0.92.channel.item[0].title".into()),
                         link: Some("rss_0.92.channel.item[0].link".into()),
                         id: None,
+                        summary: Some("rss_0.92.channel.item[0].description".into()),
+                        content: None,
+                        published: None,
+                        attachments: vec![enclosure("rss_0.92.channel.item[0].enclousure[0]")],
+                        authors: vec![],
+                        categories: vec![
+                            "rss_0.92.channel.item[0].category[0]".into(),
+                            "rss_0.92.channel.item[0].category[1]".into(),
+                        ],
                     },
                     Item {
                         title: Some("rss_0.92.channel.item[1].title".into()),
                         link: Some("rss_0.92.channel.item[1].link".into()),
                         id: None,
+                        summary: Some("rss_0.92.channel.item[1].description".into()),
+                        content: None,
+                        published: None,
+                        attachments: vec![enclosure("rss_0.92.channel.item[1].enclousure[0]")],
+                        authors: vec![],
+                        categories: vec![
+                            "rss_0.92.channel.item[1].category[0]".into(),
+                            "rss_0.92.channel.item[1].category[1]".into(),
+                        ],
                     },
                 ],
                 ..Rss::default()
@@ -619,7 +1359,7 @@ mod test {
     #[test]
     fn rss093() {
         let s = include_str!("../tests/data/rss_0.93.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None, None).unwrap();
         assert_eq!(
             r,
             Rss {
@@ -630,11 +1370,35 @@ mod test {
                         title: Some("rss_0.93.channel.item[0].title".into()),
                         link: Some("rss_0.93.channel.item[0].link".into()),
                         id: None,
+                        summary: Some("rss_0.93.channel.item[0].description".into()),
+                        content: None,
+                        published: Some(Utc.ymd(2001, 1, 1).and_hms(0, 0, 0)),
+                        attachments: vec![
+                            enclosure("rss_0.93.channel.item[0].enclousure[0]"),
+                            enclosure("rss_0.93.channel.item[0].enclousure[1]"),
+                        ],
+                        authors: vec![],
+                        categories: vec![
+                            "rss_0.93.channel.item[0].category[0]".into(),
+                            "rss_0.93.channel.item[0].category[1]".into(),
+                        ],
                     },
                     Item {
                         title: Some("rss_0.93.channel.item[1].title".into()),
                         link: Some("rss_0.93.channel.item[1].link".into()),
                         id: None,
+                        summary: Some("rss_0.93.channel.item[1].description".into()),
+                        content: None,
+                        published: Some(Utc.ymd(2001, 1, 2).and_hms(0, 0, 0)),
+                        attachments: vec![
+                            enclosure("rss_0.93.channel.item[1].enclousure[0]"),
+                            enclosure("rss_0.93.channel.item[1].enclousure[1]"),
+                        ],
+                        authors: vec![],
+                        categories: vec![
+                            "rss_0.93.channel.item[1].category[0]".into(),
+                            "rss_0.93.channel.item[1].category[1]".into(),
+                        ],
                     },
                 ],
                 ..Rss::default()
@@ -645,7 +1409,7 @@ mod test {
     #[test]
     fn rss094() {
         let s = include_str!("../tests/data/rss_0.94.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None, None).unwrap();
         assert_eq!(
             r,
             Rss {
@@ -657,11 +1421,35 @@ mod test {
                         title: Some("rss_0.94.channel.item[0].title".into()),
                         link: Some("rss_0.94.channel.item[0].link".into()),
                         id: Some("rss_0.94.channel.item[0].guid".into()),
+                        summary: Some("rss_0.94.channel.item[0].description".into()),
+                        content: None,
+                        published: Some(Utc.ymd(2001, 1, 1).and_hms(0, 0, 0)),
+                        attachments: vec![
+                            enclosure("rss_0.94.channel.item[0].enclousure[0]"),
+                            enclosure("rss_0.94.channel.item[0].enclousure[1]"),
+                        ],
+                        authors: vec!["rss_0.94.channel.item[0].author".into()],
+                        categories: vec![
+                            "rss_0.94.channel.item[0].category[0]".into(),
+                            "rss_0.94.channel.item[0].category[1]".into(),
+                        ],
                     },
                     Item {
                         title: Some("rss_0.94.channel.item[1].title".into()),
                         link: Some("rss_0.94.channel.item[1].link".into()),
                         id: Some("rss_0.94.channel.item[1].guid".into()),
+                        summary: Some("rss_0.94.channel.item[1].description".into()),
+                        content: None,
+                        published: Some(Utc.ymd(2001, 1, 2).and_hms(0, 0, 0)),
+                        attachments: vec![
+                            enclosure("rss_0.94.channel.item[1].enclousure[0]"),
+                            enclosure("rss_0.94.channel.item[1].enclousure[1]"),
+                        ],
+                        authors: vec!["rss_0.94.channel.item[1].author".into()],
+                        categories: vec![
+                            "rss_0.94.channel.item[1].category[0]".into(),
+                            "rss_0.94.channel.item[1].category[1]".into(),
+                        ],
                     },
                 ],
                 ..Rss::default()
@@ -672,7 +1460,7 @@ mod test {
     #[test]
     fn rss10() {
         let s = include_str!("../tests/data/rss_1.0.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None, None).unwrap();
         assert_eq!(
             r,
             Rss {
@@ -683,11 +1471,23 @@ mod test {
                         title: Some("rss_1.0.item[0].title".into()),
                         link: Some("rss_1.0.item[0].link".into()),
                         id: None,
+                        summary: Some("rss_1.0.item[0].description".into()),
+                        content: Some("rss_1.0.item[0].content".into()),
+                        published: None,
+                        attachments: vec![],
+                        authors: vec![],
+                        categories: vec![],
                     },
                     Item {
                         title: Some("rss_1.0.item[1].title".into()),
                         link: Some("rss_1.0.item[1].link".into()),
                         id: None,
+                        summary: Some("rss_1.0.item[1].description".into()),
+                        content: Some("rss_1.0.item[1].content".into()),
+                        published: None,
+                        attachments: vec![],
+                        authors: vec![],
+                        categories: vec![],
                     },
                 ],
                 ..Rss::default()
@@ -698,7 +1498,7 @@ mod test {
     #[test]
     fn rss20() {
         let s = include_str!("../tests/data/rss_2.0.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None, None).unwrap();
         assert_eq!(
             r,
             Rss {
@@ -710,11 +1510,35 @@ mod test {
                         title: Some("rss_2.0.channel.item[0].title".into()),
                         link: Some("rss_2.0.channel.item[0].link".into()),
                         id: Some("rss_2.0.channel.item[0].guid".into()),
+                        summary: Some("rss_2.0.channel.item[0].description".into()),
+                        content: Some("rss_2.0.channel.item[0].content".into()),
+                        published: Some(Utc.ymd(2001, 1, 1).and_hms(0, 0, 0)),
+                        attachments: vec![
+                            enclosure("rss_2.0.channel.item[0].enclousure[0]"),
+                            enclosure("rss_2.0.channel.item[0].enclousure[1]"),
+                        ],
+                        authors: vec!["rss_2.0.channel.item[0].author".into()],
+                        categories: vec![
+                            "rss_2.0.channel.item[0].category[0]".into(),
+                            "rss_2.0.channel.item[0].category[1]".into(),
+                        ],
                     },
                     Item {
                         title: Some("rss_2.0.channel.item[1].title".into()),
                         link: Some("rss_2.0.channel.item[1].link".into()),
                         id: Some("rss_2.0.channel.item[1].guid".into()),
+                        summary: Some("rss_2.0.channel.item[1].description".into()),
+                        content: Some("rss_2.0.channel.item[1].content".into()),
+                        published: Some(Utc.ymd(2001, 1, 2).and_hms(0, 0, 0)),
+                        attachments: vec![
+                            enclosure("rss_2.0.channel.item[1].enclousure[0]"),
+                            enclosure("rss_2.0.channel.item[1].enclousure[1]"),
+                        ],
+                        authors: vec!["rss_2.0.channel.item[1].author".into()],
+                        categories: vec![
+                            "rss_2.0.channel.item[1].category[0]".into(),
+                            "rss_2.0.channel.item[1].category[1]".into(),
+                        ],
                     },
                 ],
                 ..Rss::default()
@@ -730,10 +1554,150 @@ mod test {
 <atom:link href="self link" rel="self" />
 </channel>
 </rss>"#;
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None, None).unwrap();
         assert_eq!(r.source, Some("self link".into()));
     }
 
+    /// Bind unprefixed elements to a prefix, and rename the other prefixes
+    fn prefixed(xml: &[u8]) -> Vec<u8> {
+        use regex::bytes::Regex;
+        lazy_static! {
+            static ref PREFIX: Regex = Regex::new(r"(xmlns:|</?|\s)(rdf|content)([:=])").unwrap();
+            static ref DEFAULT_NS: Regex = Regex::new(r#"\sxmlns=("[^"]*"|'[^']*')"#).unwrap();
+            static ref RSS: Regex = Regex::new(r"<rss\b").unwrap();
+            static ref NAME: Regex = Regex::new(r"<(/?)([A-Za-z][\w.-]*)([\s/>])").unwrap();
+        }
+        let xml = PREFIX.replace_all(xml, &b"${1}x-$2$3"[..]);
+        let xml = if DEFAULT_NS.is_match(&xml) {
+            DEFAULT_NS.replace(&xml, &b" xmlns:p=$1"[..])
+        } else {
+            // RSS 0.91 to 2.0 have no namespace, but some feeds use the UserLand one
+            RSS.replace(
+                &xml,
+                &b"<rss xmlns:p='http://backend.userland.com/rss2'"[..],
+            )
+        };
+        NAME.replace_all(&xml, &b"<${1}p:$2$3"[..]).into_owned()
+    }
+
+    #[test]
+    fn prefixed_fixtures() {
+        let fixtures: &[&[u8]] = &[
+            include_bytes!("../tests/data/atom_0.3.xml"),
+            include_bytes!("../tests/data/atom_1.0.xml"),
+            include_bytes!("../tests/data/encoding.xml"),
+            include_bytes!("../tests/data/relative_urls.xml"),
+            include_bytes!("../tests/data/rss_0.9.xml"),
+            include_bytes!("../tests/data/rss_0.91.xml"),
+            include_bytes!("../tests/data/rss_0.92.xml"),
+            include_bytes!("../tests/data/rss_0.93.xml"),
+            include_bytes!("../tests/data/rss_0.94.xml"),
+            include_bytes!("../tests/data/rss_1.0.xml"),
+            include_bytes!("../tests/data/rss_2.0.xml"),
+            include_bytes!("../tests/data/xml_base.xml"),
+        ];
+        for fixture in fixtures {
+            let xml = prefixed(fixture);
+            assert_ne!(&xml[..], *fixture);
+            assert_eq!(
+                parse(&xml[..], None, None).unwrap(),
+                parse(*fixture, None, None).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn namespaces() {
+        let s = r#"<feed xmlns="http://www.w3.org/2005/Atom" xmlns:a="http://www.w3.org/2005/Atom"
+      xmlns:x="urn:x" xmlns:m="http://search.yahoo.com/mrss/">
+<title>feed</title>
+<x:title>not the title</x:title>
+<a:entry>
+    <a:title>entry</a:title>
+    <x:link href="http://example.com/wrong" />
+    <link href="http://example.com/entry" />
+    <m:thumbnail url="http://example.com/thumbnail" />
+    <x:id>wrong</x:id>
+</a:entry>
+<entry xmlns="urn:x"><title>not an entry</title></entry>
+</feed>"#;
+        let r = parse(Cursor::new(s), None, None).unwrap();
+        assert_eq!(r.title, "feed");
+        assert_eq!(r.items.len(), 1);
+        assert_eq!(r.items[0].title.as_deref(), Some("entry"));
+        assert_eq!(r.items[0].link.as_deref(), Some("http://example.com/entry"));
+        assert_eq!(r.items[0].id, None);
+        assert_eq!(
+            r.items[0].attachments[0].url,
+            "http://example.com/thumbnail"
+        );
+    }
+
+    #[test]
+    fn atom_text_constructs() {
+        let s = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+<title type="html">&lt;b&gt;Feed&lt;/b&gt; &amp;amp; more</title>
+<entry>
+    <title type="xhtml">
+        <div xmlns="http://www.w3.org/1999/xhtml"><b>Hello</b> <i>world</i> &amp; &lt;friends&gt;</div>
+    </title>
+    <summary type="xhtml"><x:div xmlns:x="http://www.w3.org/1999/xhtml"><x:p>summary</x:p><x:br/></x:div></summary>
+</entry>
+<entry><title type="html"><![CDATA[A <em>b</em>
+    c]]></title></entry>
+<entry><title type="text">1 &lt; 2</title></entry>
+</feed>"#;
+        let r = parse(Cursor::new(s), None, None).unwrap();
+        assert_eq!(r.title, "Feed & more");
+        let titles = r
+            .items
+            .iter()
+            .map(|i| i.title.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            vec![
+                Some("Hello world & <friends>"),
+                Some("A b c"),
+                Some("1 < 2")
+            ]
+        );
+        assert_eq!(
+            r.items[0].summary.as_deref(),
+            Some(r#"<div xmlns:x="http://www.w3.org/1999/xhtml"><p>summary</p><br/></div>"#)
+        );
+    }
+
+    #[test]
+    fn malformed_feed() {
+        let s = "<rss version=\"2.0\"><channel>
+<title>broken&nbsp;&amp; feed</title>
+<item><title>AT&T &mdash; news\u{7}</title><link>http://example.com/?a=1&b=2</link></item>
+<item><title>second</title></bad></item>
+<item><title>lost</title></item>
+</channel></rss>";
+        let r = parse(Cursor::new(s), None, None).unwrap();
+        assert_eq!(r.title, "broken\u{a0}& feed");
+        assert_eq!(r.items.len(), 1);
+        assert_eq!(r.items[0].title.as_deref(), Some("AT&T — news"));
+        assert_eq!(
+            r.items[0].link.as_deref(),
+            Some("http://example.com/?a=1&b=2")
+        );
+        assert!(r.warning.is_some());
+
+        let s = r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>truncated</title>
+<entry><title>complete</title></entry>
+<entry><title>cut"#;
+        let r = parse(Cursor::new(s), None, None).unwrap();
+        assert_eq!(r.items.len(), 1);
+        assert_eq!(r.items[0].title.as_deref(), Some("complete"));
+        assert!(r.warning.is_some());
+
+        let s = "<rss><channel></broken></channel></rss>";
+        assert!(parse(Cursor::new(s), None, None).is_err());
+    }
+
     #[test]
     fn atom_link_parsing() {
         let data = vec![
@@ -741,6 +1705,7 @@ mod test {
             r#"<link href="alternate href" rel="alternate" />"#,
             r#"<link href="self href" rel="self" />"#,
             r#"<link href="hub href" rel="hub" />"#,
+            r#"<link href="enclosure href" rel="enclosure" type="audio/mpeg" length="1" />"#,
             r#"<link href="other href" rel="other" />"#,
             r#"<link />"#,
         ];
@@ -749,6 +1714,12 @@ mod test {
             Some(AtomLink::Alternate("alternate href".into())),
             Some(AtomLink::Source("self href".into())),
             Some(AtomLink::Hub("hub href".into())),
+            Some(AtomLink::Enclosure(Attachment {
+                url: "enclosure href".into(),
+                mime_type: Some("audio/mpeg".into()),
+                length: Some(1),
+                kind: AttachmentKind::Audio,
+            })),
             Some(AtomLink::Other(
                 "other href".into(),
                 Cow::Owned("other".into()),
@@ -759,15 +1730,116 @@ mod test {
             let mut reader = XmlReader::from_reader(Cursor::new(data));
             let mut buf = Vec::new();
             if let XmlEvent::Empty(e) = reader.read_event(&mut buf).unwrap() {
-                let r = parse_atom_link(&mut reader, e.attributes()).unwrap();
+                let r = parse_atom_link(&mut reader, e.attributes(), None).unwrap();
                 assert_eq!(r, result);
             }
         }
     }
 
+    #[test]
+    fn html_fragment_to_text() {
+        let html = "<p>Status:&nbsp;<b>degraded</b></p>\n<p>A &amp; B&#33;</p><script>x()</script>";
+        assert_eq!(html_to_text(html), "Status: degraded A & B!");
+        assert_eq!(html_to_text("a<br/>b &unknown;"), "a b &unknown;");
+    }
+
+    #[test]
+    fn item_excerpt() {
+        let item = Item {
+            content: Some("<p>0123456789</p>".into()),
+            ..Item::default()
+        };
+        assert_eq!(item.excerpt(20), Some("0123456789".into()));
+        assert_eq!(item.excerpt(5), Some("0123…".into()));
+        let item = Item {
+            summary: Some("summary".into()),
+            content: Some("content".into()),
+            ..Item::default()
+        };
+        assert_eq!(item.excerpt(20), Some("summary".into()));
+        assert_eq!(Item::default().excerpt(20), None);
+    }
+
+    #[test]
+    fn date_formats() {
+        let date = Some(Utc.ymd(2001, 1, 2).and_hms(3, 4, 5));
+        assert_eq!(parse_date("Tue, 02 Jan 2001 03:04:05 GMT"), date);
+        assert_eq!(parse_date("Mon, 02 Jan 2001 03:04:05 GMT"), date);
+        assert_eq!(parse_date("02 Jan 2001 11:04:05 +0800"), date);
+        assert_eq!(parse_date(" 2001-01-02T03:04:05Z "), date);
+        assert_eq!(parse_date("2001-01-02T11:04:05+08:00"), date);
+        assert_eq!(
+            parse_date("2001-01-02"),
+            Some(Utc.ymd(2001, 1, 2).and_hms(0, 0, 0))
+        );
+        assert_eq!(parse_date("yesterday"), None);
+    }
+
+    #[test]
+    fn updated_as_published_date() {
+        let s = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+<entry><updated>2001-01-02T03:04:05Z</updated></entry>
+</feed>"#;
+        let r = parse(Cursor::new(s), None, None).unwrap();
+        assert_eq!(
+            r.items[0].published,
+            Some(Utc.ymd(2001, 1, 2).and_hms(3, 4, 5))
+        );
+    }
+
+    #[test]
+    fn media_rss() {
+        let s = r#"<rss xmlns:media="http://search.yahoo.com/mrss/"><channel><item>
+<enclosure url="http://example.com/a.mp3" length="42" type="audio/mpeg" />
+<media:content url="http://example.com/a.mp3" />
+<media:group>
+<media:content url="http://example.com/b" medium="image" fileSize="7"></media:content>
+<media:content url="http://example.com/c.JPG?size=large" />
+<media:title>skipped</media:title>
+</media:group>
+<media:thumbnail url="http://example.com/thumbnail" />
+</item></channel></rss>"#;
+        let r = parse(Cursor::new(s), None, None).unwrap();
+        let kinds = r.items[0]
+            .attachments
+            .iter()
+            .map(|a| (a.url.as_str(), a.length, a.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                ("http://example.com/a.mp3", Some(42), AttachmentKind::Audio),
+                ("http://example.com/b", Some(7), AttachmentKind::Image),
+                (
+                    "http://example.com/c.JPG?size=large",
+                    None,
+                    AttachmentKind::Image
+                ),
+                ("http://example.com/thumbnail", None, AttachmentKind::Image),
+            ]
+        );
+    }
+
+    #[test]
+    fn authors_and_categories() {
+        let s = r#"<feed xmlns="http://www.w3.org/2005/Atom"
+      xmlns:dc="http://purl.org/dc/elements/1.1/">
+<entry>
+<author><name>Alice</name><email>alice@example.com</email></author>
+<dc:creator>Bob</dc:creator>
+<dc:creator>Alice</dc:creator>
+<category term="rust" />
+<category term="release" label="Release"></category>
+</entry>
+</feed>"#;
+        let r = parse(Cursor::new(s), None, None).unwrap();
+        assert_eq!(r.items[0].authors, vec!["Alice", "Bob"]);
+        assert_eq!(r.items[0].categories, vec!["rust", "release"]);
+    }
+
     #[test]
     fn empty_input() {
-        let r = parse(Cursor::new(&[])).unwrap_err();
+        let r = parse(Cursor::new(&[]), None, None).unwrap_err();
         assert!(matches!(r, quick_xml::Error::UnexpectedEof(s) if s == "feed" ))
     }
 
@@ -780,7 +1852,7 @@ mod test {
 <sy:updateFrequency>6</sy:updateFrequency>
 </channel>
 </rss>"#;
-        let sy_output = parse(Cursor::new(sy_input)).unwrap();
+        let sy_output = parse(Cursor::new(sy_input), None, None).unwrap();
         assert_eq!(sy_output.ttl, Some(10));
     }
 
@@ -792,7 +1864,7 @@ mod test {
 <sy:updatePeriod>daily</sy:updatePeriod>
 </channel>
 </rss>"#;
-        let sy_output = parse(Cursor::new(sy_input)).unwrap();
+        let sy_output = parse(Cursor::new(sy_input), None, None).unwrap();
         assert_eq!(sy_output.ttl, Some(60 * 24));
     }
 
@@ -806,7 +1878,7 @@ mod test {
 </ttl>
 </channel>
 </rss>"#;
-        let output = parse(Cursor::new(input)).unwrap();
+        let output = parse(Cursor::new(input), None, None).unwrap();
         assert_eq!(output.ttl, Some(42));
     }
 
@@ -820,7 +1892,77 @@ mod test {
 <ttl>42</ttl>
 </channel>
 </rss>"#;
-        let output = parse(Cursor::new(input)).unwrap();
+        let output = parse(Cursor::new(input), None, None).unwrap();
         assert_eq!(output.ttl, Some(42));
     }
+
+    #[test]
+    fn websub_hub() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+<channel>
+<atom:link rel="hub" href="https://hub.example.com/" />
+<atom:link rel="self" href="https://example.com/feed.xml" type="application/rss+xml" />
+</channel>
+</rss>"#;
+        let output = parse(Cursor::new(input), None, None).unwrap();
+        assert_eq!(output.hub.as_deref(), Some("https://hub.example.com/"));
+        assert_eq!(
+            output.source.as_deref(),
+            Some("https://example.com/feed.xml")
+        );
+
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<link rel="hub" href="https://hub.example.com/"></link>
+</feed>"#;
+        let output = parse(Cursor::new(input), None, None).unwrap();
+        assert_eq!(output.hub.as_deref(), Some("https://hub.example.com/"));
+    }
+
+    fn links(rss: &Rss) -> Vec<&str> {
+        rss.items
+            .iter()
+            .map(|item| item.link.as_deref().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn relative_urls() {
+        let s = include_str!("../tests/data/relative_urls.xml");
+        let base = Url::parse("https://example.com/feeds/rss.xml").unwrap();
+        let r = resolve_relative_urls(parse(Cursor::new(s), Some(&base), None).unwrap(), &base);
+        assert_eq!(r.link, "https://example.com");
+        assert_eq!(
+            links(&r),
+            vec![
+                "https://cdn.example.com/a.html",
+                "https://example.com/posts/1.html",
+                "https://example.com/feeds/posts/1.html",
+                "https://example.com/a.html",
+                "http://example.org/Not%20Normalized",
+            ]
+        );
+        assert_eq!(
+            r.items[3].attachments[0].url,
+            "https://example.com/feeds/media/b.mp3"
+        );
+    }
+
+    #[test]
+    fn xml_base() {
+        let s = include_str!("../tests/data/xml_base.xml");
+        let base = Url::parse("https://example.com/feeds/atom.xml").unwrap();
+        let r = resolve_relative_urls(parse(Cursor::new(s), Some(&base), None).unwrap(), &base);
+        assert_eq!(r.link, "https://example.com/blog/");
+        assert_eq!(
+            links(&r),
+            vec![
+                "https://example.com/blog/2020/post.html",
+                "https://other.example.com/y.html",
+                "https://cdn.example.com/z.html",
+                "https://example.org/absolute.html",
+            ]
+        );
+    }
 }
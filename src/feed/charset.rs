@@ -0,0 +1,228 @@
+//! Feeds are transcoded into UTF-8 before parsing, the charset is decided by
+//! the BOM, the `Content-Type` charset, the XML declaration and a guess, in that order
+
+use std::io::{self, BufRead, Read};
+
+use encoding_rs::{
+    CoderResult, Decoder, Encoding, GB18030, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252,
+};
+use lazy_static::lazy_static;
+use regex::bytes::Regex;
+
+/// Enough for the BOM, the XML declaration and a guess
+const HEAD_LEN: usize = 1024;
+
+lazy_static! {
+    static ref XML_DECL_ENCODING: Regex =
+        Regex::new(r#"^\s*<\?xml[^>]*?\sencoding\s*=\s*["']([A-Za-z0-9._:-]+)["']"#).unwrap();
+}
+
+/// Transcode a feed into UTF-8, the XML declaration is changed to match
+pub struct Utf8Reader<R> {
+    inner: R,
+    decoder: Decoder,
+    buf: Vec<u8>,
+    pos: usize,
+    finished: bool,
+}
+
+impl<R: BufRead> Utf8Reader<R> {
+    /// `charset` is the one from the `Content-Type` header
+    pub fn new(mut inner: R, charset: Option<&str>) -> io::Result<Self> {
+        let mut head = Vec::with_capacity(HEAD_LEN);
+        while head.len() < HEAD_LEN {
+            let chunk = inner.fill_buf()?;
+            if chunk.is_empty() {
+                break;
+            }
+            let len = chunk.len().min(HEAD_LEN - head.len());
+            head.extend_from_slice(&chunk[..len]);
+            inner.consume(len);
+        }
+        let (encoding, bom_len) = detect(&head, charset);
+        let mut reader = Utf8Reader {
+            inner,
+            decoder: encoding.new_decoder_without_bom_handling(),
+            buf: Vec::new(),
+            pos: 0,
+            finished: false,
+        };
+        reader.finished = head.len() < HEAD_LEN;
+        decode(
+            &mut reader.decoder,
+            &mut reader.buf,
+            &head[bom_len..],
+            reader.finished,
+        );
+        // quick-xml would decode the text again with the declared encoding
+        let declared = XML_DECL_ENCODING
+            .captures(&reader.buf)
+            .and_then(|c| c.get(1))
+            .map(|m| m.range());
+        if let Some(range) = declared {
+            reader.buf.splice(range, b"UTF-8".iter().copied());
+        }
+        Ok(reader)
+    }
+}
+
+fn decode(decoder: &mut Decoder, dst: &mut Vec<u8>, src: &[u8], last: bool) {
+    let max_len = decoder
+        .max_utf8_buffer_length(src.len())
+        .expect("chunk too large");
+    dst.clear();
+    dst.resize(max_len, 0);
+    let (result, read, written, _) = decoder.decode_to_utf8(src, dst, last);
+    debug_assert_eq!(result, CoderResult::InputEmpty);
+    debug_assert_eq!(read, src.len());
+    dst.truncate(written);
+}
+
+impl<R: BufRead> Read for Utf8Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for Utf8Reader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // a chunk may only have a part of a character
+        while self.pos >= self.buf.len() && !self.finished {
+            let chunk = self.inner.fill_buf()?;
+            let len = chunk.len();
+            self.finished = len == 0;
+            decode(&mut self.decoder, &mut self.buf, chunk, self.finished);
+            self.inner.consume(len);
+            self.pos = 0;
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+/// Transcode a whole feed into UTF-8
+pub fn to_utf8(body: &[u8], charset: Option<&str>) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(body.len());
+    Utf8Reader::new(body, charset)?.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// The encoding of a feed and the length of its BOM
+fn detect(head: &[u8], charset: Option<&str>) -> (&'static Encoding, usize) {
+    if let Some(bom) = Encoding::for_bom(head) {
+        return bom;
+    }
+    let declared = charset
+        .and_then(|charset| Encoding::for_label(charset.trim().as_bytes()))
+        .or_else(|| {
+            let label = XML_DECL_ENCODING.captures(head)?.get(1)?.as_bytes();
+            // the declaration was read as ASCII, so it can't be UTF-16
+            Encoding::for_label(label).map(|encoding| match encoding {
+                e if e == UTF_16LE || e == UTF_16BE => UTF_8,
+                e => e,
+            })
+        });
+    (declared.unwrap_or_else(|| guess(head)), 0)
+}
+
+/// UTF-16 is told by the zero bytes of `<?`, otherwise it's UTF-8 if it's valid,
+/// then GB18030 (a superset of GBK) and Windows-1252
+fn guess(head: &[u8]) -> &'static Encoding {
+    if head.starts_with(b"<\0?\0") {
+        return UTF_16LE;
+    } else if head.starts_with(b"\0<\0?") {
+        return UTF_16BE;
+    }
+    let is_valid = |encoding: &'static Encoding| {
+        let mut decoder = encoding.new_decoder_without_bom_handling();
+        let mut dst = String::with_capacity(
+            decoder
+                .max_utf8_buffer_length_without_replacement(head.len())
+                .unwrap_or_default(),
+        );
+        // the head may end in the middle of a character, which is fine
+        let (result, _) = decoder.decode_to_string_without_replacement(head, &mut dst, false);
+        result == encoding_rs::DecoderResult::InputEmpty
+    };
+    [UTF_8, GB18030]
+        .iter()
+        .copied()
+        .find(|&encoding| is_valid(encoding))
+        .unwrap_or(WINDOWS_1252)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn title(body: &[u8], charset: Option<&str>) -> String {
+        let reader = Utf8Reader::new(body, charset).unwrap();
+        super::super::parse(reader, None, None).unwrap().title
+    }
+
+    #[test]
+    fn encodings() {
+        let cases: &[(&[u8], Option<&str>)] = &[
+            // XML declaration
+            (include_bytes!("../../tests/data/encoding.xml"), None),
+            // BOM, the declaration says UTF-16
+            (
+                include_bytes!("../../tests/data/encoding_utf16le_bom.xml"),
+                None,
+            ),
+            (
+                include_bytes!("../../tests/data/encoding_utf16be_bom.xml"),
+                None,
+            ),
+            // BOM over the header and the declaration
+            (
+                include_bytes!("../../tests/data/encoding_utf8_bom.xml"),
+                Some("GBK"),
+            ),
+            // header, without a declaration
+            (
+                include_bytes!("../../tests/data/encoding_gbk.xml"),
+                Some("GBK"),
+            ),
+            // header over the declaration
+            (
+                include_bytes!("../../tests/data/encoding_wrong_decl.xml"),
+                Some("utf-8"),
+            ),
+            // guesses
+            (include_bytes!("../../tests/data/encoding_gbk.xml"), None),
+            (
+                include_bytes!("../../tests/data/encoding_utf16le.xml"),
+                None,
+            ),
+        ];
+        for (body, charset) in cases {
+            assert_eq!(title(body, *charset), "虎扑足球新闻");
+        }
+    }
+
+    #[test]
+    fn guess_latin() {
+        let body = b"<rss><channel><title>caf\xe9 cr\xe8me</title></channel></rss>";
+        assert_eq!(title(body, None), "café crème");
+    }
+
+    #[test]
+    fn small_chunks() {
+        let body: &[u8] = include_bytes!("../../tests/data/encoding_utf16be_bom.xml");
+        // every character is split between reads
+        let reader = io::BufReader::with_capacity(1, body);
+        let reader = Utf8Reader::new(reader, None).unwrap();
+        assert_eq!(
+            super::super::parse(reader, None, None).unwrap().title,
+            "虎扑足球新闻"
+        );
+    }
+}
@@ -0,0 +1,548 @@
+//! JSON Feed 1.0 and 1.1
+//!
+//! https://jsonfeed.org/version/1.1
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::Read;
+
+use serde::de::{DeserializeSeed, Deserializer, Error, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{parse_date, Attachment, AttachmentKind, Item, Rss};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Version {
+    V1,
+    V1_1,
+}
+
+impl TryFrom<String> for Version {
+    type Error = String;
+
+    fn try_from(version: String) -> Result<Self, Self::Error> {
+        // some feeds use http instead of https
+        let number = version
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_start_matches("jsonfeed.org/version/")
+            .trim_end_matches('/');
+        match number {
+            "1" | "1.0" => Ok(Version::V1),
+            "1.1" => Ok(Version::V1_1),
+            _ => Err(format!("unsupported JSON Feed version: {}", version)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JsonFeed {
+    pub version: Version,
+    pub title: String,
+    pub home_page_url: Option<String>,
+    pub feed_url: Option<String>,
+    /// Only the first page is fetched, it always has the newest items
+    pub next_url: Option<String>,
+    /// Deprecated in 1.1
+    pub author: Option<Author>,
+    pub authors: Vec<Author>,
+    pub hubs: Vec<Hub>,
+    pub items: Vec<JsonItem>,
+    /// Keys start with `_`
+    pub extensions: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct JsonItem {
+    /// Should be a string, but numbers are common in the wild
+    pub id: Option<Value>,
+    pub url: Option<String>,
+    pub external_url: Option<String>,
+    pub title: Option<String>,
+    pub content_html: Option<String>,
+    pub content_text: Option<String>,
+    pub summary: Option<String>,
+    pub image: Option<String>,
+    pub date_published: Option<String>,
+    pub date_modified: Option<String>,
+    /// Deprecated in 1.1
+    pub author: Option<Author>,
+    pub authors: Vec<Author>,
+    pub tags: Vec<String>,
+    pub attachments: Vec<JsonAttachment>,
+    /// Keys start with `_`
+    pub extensions: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Author {
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub avatar: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Hub {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JsonAttachment {
+    pub url: String,
+    pub mime_type: Option<String>,
+    pub title: Option<String>,
+    pub size_in_bytes: Option<u64>,
+    pub duration_in_seconds: Option<f64>,
+}
+
+/// Visits the keys of an object, `field` reads the value of those it knows,
+/// the other ones are kept in `extensions` if they start with `_`, or skipped
+fn visit_fields<'de, A, F>(
+    mut map: A,
+    extensions: &mut HashMap<String, Value>,
+    mut field: F,
+) -> Result<(), A::Error>
+where
+    A: MapAccess<'de>,
+    F: FnMut(&str, &mut A) -> Result<bool, A::Error>,
+{
+    while let Some(key) = map.next_key::<String>()? {
+        if field(&key, &mut map)? {
+            continue;
+        }
+        if key.starts_with('_') {
+            let value = map.next_value()?;
+            extensions.insert(key, value);
+        } else {
+            map.next_value::<IgnoredAny>()?;
+        }
+    }
+    Ok(())
+}
+
+impl<'de> Deserialize<'de> for JsonItem {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ItemVisitor;
+
+        impl<'de> Visitor<'de> for ItemVisitor {
+            type Value = JsonItem;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an item")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let mut item = JsonItem::default();
+                let mut extensions = HashMap::new();
+                visit_fields(map, &mut extensions, |key, map| {
+                    match key {
+                        "id" => item.id = map.next_value()?,
+                        "url" => item.url = map.next_value()?,
+                        "external_url" => item.external_url = map.next_value()?,
+                        "title" => item.title = map.next_value()?,
+                        "content_html" => item.content_html = map.next_value()?,
+                        "content_text" => item.content_text = map.next_value()?,
+                        "summary" => item.summary = map.next_value()?,
+                        "image" => item.image = map.next_value()?,
+                        "date_published" => item.date_published = map.next_value()?,
+                        "date_modified" => item.date_modified = map.next_value()?,
+                        "author" => item.author = map.next_value()?,
+                        "authors" => item.authors = map.next_value()?,
+                        "tags" => item.tags = map.next_value()?,
+                        "attachments" => item.attachments = map.next_value()?,
+                        _ => return Ok(false),
+                    }
+                    Ok(true)
+                })?;
+                item.extensions = extensions;
+                Ok(item)
+            }
+        }
+
+        deserializer.deserialize_map(ItemVisitor)
+    }
+}
+
+/// Keeps the first `max` items, the rest are skipped, or not read at all
+/// if `stop` is set, then it fails after setting `stopped`
+struct Items<'a> {
+    items: &'a mut Vec<JsonItem>,
+    max: usize,
+    stop: bool,
+    stopped: &'a mut bool,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for Items<'a> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for Items<'a> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of items")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while self.items.len() < self.max {
+            match seq.next_element()? {
+                Some(item) => self.items.push(item),
+                None => return Ok(()),
+            }
+        }
+        if self.stop {
+            *self.stopped = true;
+            return Err(A::Error::custom("stopped after the last item"));
+        }
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(())
+    }
+}
+
+/// Keeps the first `max_items` items, the input after them isn't read if the
+/// required keys are known by then, the feed is left in `stopped` instead
+struct FeedSeed<'a> {
+    max_items: usize,
+    stopped: &'a mut Option<JsonFeed>,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for FeedSeed<'a> {
+    type Value = JsonFeed;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<JsonFeed, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for FeedSeed<'a> {
+    type Value = JsonFeed;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON Feed")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<JsonFeed, A::Error> {
+        let max_items = self.max_items;
+        let (mut version, mut title, mut items) = (None, None, None);
+        let (mut home_page_url, mut feed_url, mut next_url) = (None, None, None);
+        let (mut author, mut authors, mut hubs) = (None, Vec::new(), Vec::new());
+        let mut extensions = HashMap::new();
+        let mut stopped = false;
+        let result = visit_fields(map, &mut extensions, |key, map| {
+            match key {
+                "version" => version = Some(map.next_value()?),
+                "title" => title = Some(map.next_value()?),
+                "home_page_url" => home_page_url = map.next_value()?,
+                "feed_url" => feed_url = map.next_value()?,
+                "next_url" => next_url = map.next_value()?,
+                "author" => author = map.next_value()?,
+                "authors" => authors = map.next_value()?,
+                "hubs" => hubs = map.next_value()?,
+                "items" => {
+                    let stop = version.is_some() && title.is_some();
+                    map.next_value_seed(Items {
+                        items: items.get_or_insert_with(Vec::new),
+                        max: max_items,
+                        stop,
+                        stopped: &mut stopped,
+                    })?;
+                }
+                _ => return Ok(false),
+            }
+            Ok(true)
+        });
+        let stopped_by = match result {
+            Ok(()) => None,
+            Err(e) if stopped => Some(e),
+            Err(e) => return Err(e),
+        };
+        let feed = JsonFeed {
+            version: version.ok_or_else(|| A::Error::missing_field("version"))?,
+            title: title.ok_or_else(|| A::Error::missing_field("title"))?,
+            home_page_url,
+            feed_url,
+            next_url,
+            author,
+            authors,
+            hubs,
+            items: items.ok_or_else(|| A::Error::missing_field("items"))?,
+            extensions,
+        };
+        match stopped_by {
+            Some(e) => {
+                *self.stopped = Some(feed);
+                Err(e)
+            }
+            None => Ok(feed),
+        }
+    }
+}
+
+pub fn parse(json: &[u8]) -> serde_json::Result<JsonFeed> {
+    parse_reader(json, None)
+}
+
+/// Only the first `max_items` items are kept
+pub fn parse_reader<R: Read>(reader: R, max_items: Option<usize>) -> serde_json::Result<JsonFeed> {
+    let mut stopped = None;
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let seed = FeedSeed {
+        max_items: max_items.unwrap_or(usize::MAX),
+        stopped: &mut stopped,
+    };
+    match seed.deserialize(&mut deserializer) {
+        Ok(feed) => deserializer.end().map(|()| feed),
+        Err(e) => stopped.ok_or(e),
+    }
+}
+
+fn author_names(author: Option<Author>, authors: Vec<Author>) -> Vec<String> {
+    author
+        .into_iter()
+        .chain(authors)
+        .filter_map(|author| author.name)
+        .collect()
+}
+
+impl From<JsonFeed> for Rss {
+    fn from(feed: JsonFeed) -> Self {
+        let feed_authors = author_names(feed.author, feed.authors);
+        let items = feed
+            .items
+            .into_iter()
+            .map(|item| {
+                let mut item = Item::from(item);
+                // items without authors are written by the authors of the feed
+                if item.authors.is_empty() {
+                    item.authors = feed_authors.clone();
+                }
+                item
+            })
+            .collect();
+        Rss {
+            title: feed.title,
+            link: feed.home_page_url.unwrap_or_default(),
+            source: feed.feed_url,
+            hub: feed
+                .hubs
+                .into_iter()
+                .find(|hub| hub.type_.eq_ignore_ascii_case("WebSub"))
+                .map(|hub| hub.url),
+            ttl: None,
+            items,
+            warning: None,
+        }
+    }
+}
+
+impl From<JsonItem> for Item {
+    fn from(item: JsonItem) -> Self {
+        let id = item.id.and_then(|id| match id {
+            Value::String(s) => Some(s),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        });
+        let mut attachments: Vec<Attachment> = item
+            .attachments
+            .into_iter()
+            .map(|a| {
+                Attachment::new(
+                    a.url,
+                    a.mime_type,
+                    a.size_in_bytes,
+                    None,
+                    AttachmentKind::Other,
+                )
+            })
+            .collect();
+        if let Some(image) = item.image {
+            if attachments.iter().all(|a| a.url != image) {
+                attachments.push(Attachment::new(
+                    image,
+                    None,
+                    None,
+                    None,
+                    AttachmentKind::Image,
+                ));
+            }
+        }
+        Item {
+            title: item.title,
+            link: item.url.or(item.external_url),
+            id,
+            summary: item.summary,
+            content: item.content_html.or(item.content_text),
+            published: item
+                .date_published
+                .or(item.date_modified)
+                .and_then(|s| parse_date(&s)),
+            attachments,
+            authors: author_names(item.author, item.authors),
+            categories: item.tags,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    #[test]
+    fn json_feed_10() {
+        let s = include_bytes!("../../tests/data/json_feed_1.0.json");
+        let feed = parse(s).unwrap();
+        assert_eq!(feed.version, Version::V1);
+        assert_eq!(
+            Rss::from(feed),
+            Rss {
+                title: "json_feed_1.0.title".into(),
+                link: "json_feed_1.0.home_page_url".into(),
+                source: Some("json_feed_1.0.feed_url".into()),
+                items: vec![
+                    Item {
+                        title: Some("json_feed_1.0.items[0].title".into()),
+                        link: Some("json_feed_1.0.items[0].url".into()),
+                        id: Some("json_feed_1.0.items[0].id".into()),
+                        summary: Some("json_feed_1.0.items[0].summary".into()),
+                        content: Some("json_feed_1.0.items[0].content_html".into()),
+                        published: Some(Utc.ymd(2001, 1, 1).and_hms(0, 0, 0)),
+                        attachments: vec![Attachment {
+                            url: "json_feed_1.0.items[0].attachments[0].url".into(),
+                            mime_type: Some("audio/mpeg".into()),
+                            length: Some(100),
+                            kind: AttachmentKind::Audio,
+                        }],
+                        authors: vec!["json_feed_1.0.items[0].author.name".into()],
+                        categories: vec![
+                            "json_feed_1.0.items[0].tags[0]".into(),
+                            "json_feed_1.0.items[0].tags[1]".into(),
+                        ],
+                    },
+                    Item {
+                        title: None,
+                        link: Some("json_feed_1.0.items[1].external_url".into()),
+                        id: Some("1".into()),
+                        summary: None,
+                        content: Some("json_feed_1.0.items[1].content_text".into()),
+                        published: Some(Utc.ymd(2001, 1, 2).and_hms(0, 0, 0)),
+                        attachments: vec![],
+                        authors: vec!["json_feed_1.0.author.name".into()],
+                        categories: vec![],
+                    },
+                ],
+                ..Rss::default()
+            }
+        );
+    }
+
+    #[test]
+    fn json_feed_11() {
+        let s = include_bytes!("../../tests/data/json_feed_1.1.json");
+        let feed = parse(s).unwrap();
+        assert_eq!(feed.version, Version::V1_1);
+        assert_eq!(feed.next_url, Some("json_feed_1.1.next_url".into()));
+        assert_eq!(
+            feed.extensions.keys().collect::<Vec<_>>(),
+            vec!["_json_feed_1.1.extension"]
+        );
+        assert_eq!(
+            feed.items[0].extensions["_json_feed_1.1.items[0].extension"]["about"],
+            "json_feed_1.1.items[0].extension.about"
+        );
+        assert_eq!(
+            Rss::from(feed),
+            Rss {
+                title: "json_feed_1.1.title".into(),
+                link: "json_feed_1.1.home_page_url".into(),
+                source: Some("json_feed_1.1.feed_url".into()),
+                hub: Some("json_feed_1.1.hubs[1].url".into()),
+                items: vec![
+                    Item {
+                        title: Some("json_feed_1.1.items[0].title".into()),
+                        link: Some("json_feed_1.1.items[0].url".into()),
+                        id: Some("json_feed_1.1.items[0].id".into()),
+                        summary: None,
+                        content: Some("json_feed_1.1.items[0].content_html".into()),
+                        published: Some(Utc.ymd(2001, 1, 1).and_hms(0, 0, 0)),
+                        attachments: vec![Attachment {
+                            url: "json_feed_1.1.items[0].image".into(),
+                            mime_type: None,
+                            length: None,
+                            kind: AttachmentKind::Image,
+                        }],
+                        authors: vec![
+                            "json_feed_1.1.items[0].authors[0].name".into(),
+                            "json_feed_1.1.items[0].authors[1].name".into(),
+                        ],
+                        categories: vec![],
+                    },
+                    Item {
+                        title: Some("json_feed_1.1.items[1].title".into()),
+                        link: None,
+                        id: Some("json_feed_1.1.items[1].id".into()),
+                        summary: None,
+                        content: Some("json_feed_1.1.items[1].content_text".into()),
+                        published: Some(Utc.ymd(2001, 1, 2).and_hms(0, 0, 0)),
+                        attachments: vec![],
+                        authors: vec!["json_feed_1.1.authors[0].name".into()],
+                        categories: vec![],
+                    },
+                ],
+                ..Rss::default()
+            }
+        );
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let s = br#"{"version": "https://jsonfeed.org/version/2", "title": "", "items": []}"#;
+        assert!(parse(s).is_err());
+        let s = br#"{"title": "", "items": []}"#;
+        assert!(parse(s).is_err());
+    }
+
+    #[test]
+    fn version() {
+        for (s, version) in &[
+            ("https://jsonfeed.org/version/1", Version::V1),
+            ("http://jsonfeed.org/version/1.0/", Version::V1),
+            ("https://jsonfeed.org/version/1.1", Version::V1_1),
+        ] {
+            assert_eq!(Version::try_from(s.to_string()), Ok(*version));
+        }
+    }
+
+    #[test]
+    fn max_items() {
+        let s = include_bytes!("../../tests/data/json_feed_1.1.json");
+        let feed = parse_reader(&s[..], Some(1)).unwrap();
+        assert_eq!(feed.items.len(), 1);
+        assert_eq!(
+            feed.items[0].title.as_deref(),
+            Some("json_feed_1.1.items[0].title")
+        );
+        assert_eq!(parse(s).unwrap().items.len(), 2);
+
+        // the rest isn't read at all
+        let s = br#"{"version": "1.1", "title": "t", "_a": 1, "items": [{"id": 1}, {"#;
+        let feed = parse_reader(&s[..], Some(1)).unwrap();
+        assert_eq!(feed.items.len(), 1);
+        assert_eq!(feed.extensions.keys().collect::<Vec<_>>(), vec!["_a"]);
+        assert!(parse_reader(&s[..], Some(2)).is_err());
+        // unless the title is still missing
+        let s = br#"{"version": "1.1", "items": [{"id": 1}, {"id": 2}], "title": "t"}"#;
+        let feed = parse_reader(&s[..], Some(1)).unwrap();
+        assert_eq!((feed.title.as_str(), feed.items.len()), ("t", 1));
+    }
+}
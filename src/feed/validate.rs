@@ -0,0 +1,296 @@
+//! Problems of a feed which don't stop it from being parsed,
+//! but make it behave badly in the bot
+
+use std::collections::HashSet;
+
+use chrono::{Duration, Utc};
+use quick_xml::events::Event as XmlEvent;
+use url::Url;
+
+use super::{json::JsonFeed, parse_date, BufPool, FromXml, Ns, NsReader, Rss};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Index of the item, `None` is the feed itself
+    pub item: Option<usize>,
+    pub problem: Problem,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    EmptyFeedTitle,
+    MissingFeedLink,
+    NoItems,
+    /// Items are told apart by title and link instead
+    MissingId,
+    DuplicateId(String),
+    EmptyTitle,
+    MissingLink,
+    /// No id, title or link, all such items look the same
+    Unidentifiable,
+    RelativeLink(String),
+    MissingDate,
+    InvalidDate(String),
+    FutureDate(String),
+    /// The feed is read up to the error
+    Malformed(String),
+}
+
+impl Diagnostic {
+    fn feed(problem: Problem) -> Self {
+        Diagnostic {
+            item: None,
+            problem,
+        }
+    }
+
+    fn item(index: usize, problem: Problem) -> Self {
+        Diagnostic {
+            item: Some(index),
+            problem,
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self.problem {
+            Problem::MissingFeedLink
+            | Problem::MissingId
+            | Problem::EmptyTitle
+            | Problem::MissingDate => Severity::Info,
+            Problem::EmptyFeedTitle
+            | Problem::NoItems
+            | Problem::MissingLink
+            | Problem::RelativeLink(_)
+            | Problem::InvalidDate(_)
+            | Problem::FutureDate(_) => Severity::Warning,
+            Problem::DuplicateId(_) | Problem::Unidentifiable | Problem::Malformed(_) => {
+                Severity::Error
+            }
+        }
+    }
+}
+
+impl Problem {
+    pub fn to_user_friendly(&self) -> String {
+        match self {
+            Self::EmptyFeedTitle => tr!("validate_empty_feed_title").into(),
+            Self::MissingFeedLink => tr!("validate_missing_feed_link").into(),
+            Self::NoItems => tr!("validate_no_items").into(),
+            Self::MissingId => tr!("validate_missing_id").into(),
+            Self::DuplicateId(id) => tr!("validate_duplicate_id", id = id),
+            Self::EmptyTitle => tr!("validate_empty_title").into(),
+            Self::MissingLink => tr!("validate_missing_link").into(),
+            Self::Unidentifiable => tr!("validate_unidentifiable").into(),
+            Self::RelativeLink(link) => tr!("validate_relative_link", link = link),
+            Self::MissingDate => tr!("validate_missing_date").into(),
+            Self::InvalidDate(date) => tr!("validate_invalid_date", date = date),
+            Self::FutureDate(date) => tr!("validate_future_date", date = date),
+            Self::Malformed(error) => tr!("validate_malformed", error = error),
+        }
+    }
+}
+
+/// Check a feed parsed without a base URL, so relative links are kept
+pub fn validate(rss: &Rss) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    if let Some(warning) = &rss.warning {
+        diagnostics.push(Diagnostic::feed(Problem::Malformed(warning.clone())));
+    }
+    if rss.title.trim().is_empty() {
+        diagnostics.push(Diagnostic::feed(Problem::EmptyFeedTitle));
+    }
+    if rss.link.trim().is_empty() {
+        diagnostics.push(Diagnostic::feed(Problem::MissingFeedLink));
+    } else if is_relative(&rss.link) {
+        diagnostics.push(Diagnostic::feed(Problem::RelativeLink(rss.link.clone())));
+    }
+    if rss.items.is_empty() {
+        diagnostics.push(Diagnostic::feed(Problem::NoItems));
+    }
+
+    let mut ids = HashSet::new();
+    let future = Utc::now() + Duration::days(1);
+    for (i, item) in rss.items.iter().enumerate() {
+        let has_title = matches!(&item.title, Some(title) if !title.trim().is_empty());
+        match &item.id {
+            Some(id) if !ids.insert(id) => {
+                diagnostics.push(Diagnostic::item(i, Problem::DuplicateId(id.clone())))
+            }
+            Some(_) => (),
+            None if !has_title && item.link.is_none() => {
+                diagnostics.push(Diagnostic::item(i, Problem::Unidentifiable))
+            }
+            None => diagnostics.push(Diagnostic::item(i, Problem::MissingId)),
+        }
+        if !has_title {
+            diagnostics.push(Diagnostic::item(i, Problem::EmptyTitle));
+        }
+        match &item.link {
+            Some(link) if is_relative(link) => {
+                diagnostics.push(Diagnostic::item(i, Problem::RelativeLink(link.clone())))
+            }
+            Some(_) => (),
+            None => diagnostics.push(Diagnostic::item(i, Problem::MissingLink)),
+        }
+        match item.published {
+            Some(date) if date > future => {
+                diagnostics.push(Diagnostic::item(i, Problem::FutureDate(date.to_rfc3339())))
+            }
+            Some(_) => (),
+            None => diagnostics.push(Diagnostic::item(i, Problem::MissingDate)),
+        }
+    }
+    diagnostics
+}
+
+/// Invalid dates are dropped by the parser, so they are checked in the source,
+/// up to the first XML error
+pub fn check_xml_dates(xml: &[u8]) -> Vec<Diagnostic> {
+    let mut reader = NsReader::from_reader(xml);
+    let bufs = BufPool::new(2, 512);
+    let mut buf = bufs.pop();
+    let mut diagnostics = Vec::new();
+    let mut items = 0;
+    let mut in_item = false;
+    let mut read = || -> quick_xml::Result<()> {
+        loop {
+            match reader.read_event(&mut buf)? {
+                (ns, XmlEvent::Start(ref e)) => match (ns, &*reader.decode(e.local_name())) {
+                    (Ns::Feed, "item") | (Ns::Feed, "entry") => in_item = true,
+                    (Ns::Feed, "pubDate")
+                    | (Ns::Dc, "date")
+                    | (Ns::Feed, "published")
+                    | (Ns::Feed, "issued")
+                    | (Ns::Feed, "updated")
+                    | (Ns::Feed, "modified")
+                        if in_item =>
+                    {
+                        let date = <Option<String> as FromXml>::from_xml(&bufs, &mut reader, e)?;
+                        match date {
+                            Some(date) if !date.is_empty() && parse_date(&date).is_none() => {
+                                diagnostics
+                                    .push(Diagnostic::item(items, Problem::InvalidDate(date)))
+                            }
+                            _ => (),
+                        }
+                    }
+                    _ => (),
+                },
+                (_, XmlEvent::End(ref e)) => {
+                    if let "item" | "entry" = &*reader.decode(e.local_name()) {
+                        in_item = false;
+                        items += 1;
+                    }
+                }
+                (_, XmlEvent::Eof) => break,
+                _ => (),
+            }
+            buf.clear();
+        }
+        Ok(())
+    };
+    // the error itself is reported by `validate`
+    read().ok();
+    diagnostics
+}
+
+pub fn check_json_dates(feed: &JsonFeed) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (i, item) in feed.items.iter().enumerate() {
+        for date in item.date_published.iter().chain(&item.date_modified) {
+            if parse_date(date).is_none() {
+                diagnostics.push(Diagnostic::item(i, Problem::InvalidDate(date.clone())));
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Merge the diagnostics of the source and the parsed feed, feed problems first
+pub fn merge(source: Vec<Diagnostic>, parsed: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    let invalid_dates: HashSet<_> = source
+        .iter()
+        .filter(|d| matches!(d.problem, Problem::InvalidDate(_)))
+        .map(|d| d.item)
+        .collect();
+    let mut diagnostics: Vec<_> = source
+        .into_iter()
+        .chain(parsed.into_iter().filter(|d| {
+            // an invalid date is already reported
+            d.problem != Problem::MissingDate || !invalid_dates.contains(&d.item)
+        }))
+        .collect();
+    diagnostics.sort_by_key(|d| (d.item, std::cmp::Reverse(d.severity())));
+    diagnostics
+}
+
+fn is_relative(link: &str) -> bool {
+    Url::parse(link.trim()) == Err(url::ParseError::RelativeUrlWithoutBase)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn feed_problems() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0">
+<channel>
+<title>title</title>
+<link>https://example.com/</link>
+<item>
+    <title>ok</title>
+    <link>https://example.com/1</link>
+    <guid>1</guid>
+    <pubDate>Sat, 01 Jan 2000 00:00:00 +0000</pubDate>
+</item>
+<item>
+    <title>duplicate</title>
+    <link>/2</link>
+    <guid>1</guid>
+    <pubDate>yesterday</pubDate>
+</item>
+<item>
+    <description>nothing to identify it</description>
+</item>
+</channel>
+</rss>"#;
+        let rss = super::super::parse(Cursor::new(xml), None, None).unwrap();
+        let diagnostics = merge(check_xml_dates(xml.as_bytes()), validate(&rss));
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::item(1, Problem::DuplicateId("1".into())),
+                Diagnostic::item(1, Problem::InvalidDate("yesterday".into())),
+                Diagnostic::item(1, Problem::RelativeLink("/2".into())),
+                Diagnostic::item(2, Problem::Unidentifiable),
+                Diagnostic::item(2, Problem::MissingLink),
+                Diagnostic::item(2, Problem::EmptyTitle),
+                Diagnostic::item(2, Problem::MissingDate),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_feed() {
+        let rss = Rss::default();
+        assert_eq!(
+            validate(&rss),
+            vec![
+                Diagnostic::feed(Problem::EmptyFeedTitle),
+                Diagnostic::feed(Problem::MissingFeedLink),
+                Diagnostic::feed(Problem::NoItems),
+            ]
+        );
+    }
+}
@@ -1,13 +1,19 @@
 use std::cmp;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc, Mutex,
 };
+use std::time::SystemTime;
 
 use futures::{future::FutureExt, select_biased};
+use reqwest::StatusCode;
 use tbot::{
-    types::parameters::{self, WebPagePreviewState},
+    types::{
+        input_file::{self, GroupMedia},
+        parameters::{self, WebPagePreviewState},
+    },
     Bot,
 };
 use tokio::{
@@ -17,10 +23,12 @@ use tokio::{
     time::{self, delay_for, delay_queue::DelayQueue, Duration, Instant},
 };
 
-use crate::client::pull_feed;
-use crate::data::{Database, Feed, FeedSettings, FeedUpdate, SubscriberId};
+use crate::client::{pull_feed_if_modified, FeedError};
+use crate::constant::{EXCERPT_MAX_LEN, TELEGRAM_MAX_ALBUM_LEN, TELEGRAM_MAX_CAPTION_LEN};
+use crate::data::{Database, Feed, FeedSettings, FeedUpdate, SentMessage, SubscriberId};
 use crate::feed;
-use crate::messages::{format_large_msg, Escape};
+use crate::messages::{format_hashtag, format_large_msg, text_len, Escape};
+use crate::watch::Watch;
 
 pub fn start(bot: Bot, db: Arc<Mutex<Database>>, min_interval: u32, max_interval: u32) {
     let mut queue = FetchQueue::new();
@@ -44,15 +52,15 @@ pub fn start(bot: Bot, db: Arc<Mutex<Database>>, min_interval: u32, max_interval
                     });
                 }
                 _ = interval.tick().fuse() => {
-                    let feeds = db.lock().unwrap().all_feeds();
+                    let (feeds, request_options) = {
+                        let db = db.lock().unwrap();
+                        (db.all_feeds(), db.all_request_options())
+                    };
+                    crate::client::evict_clients(&request_options);
                     for feed in feeds {
-                        let feed_interval = cmp::min(
-                            cmp::max(
-                                feed.ttl.map(|ttl| ttl * 60).unwrap_or_default(),
-                                min_interval,
-                            ),
-                            max_interval,
-                        ) as u64 - 1; // after -1, we can stagger with `interval`
+                        // after -1, we can stagger with `interval`
+                        let feed_interval =
+                            fetch_interval(&feed, min_interval, max_interval) as u64 - 1;
                         queue.enqueue(feed, Duration::from_secs(feed_interval));
                     }
                 }
@@ -61,19 +69,96 @@ pub fn start(bot: Bot, db: Arc<Mutex<Database>>, min_interval: u32, max_interval
     });
 }
 
+/// Seconds before the next fetch of `feed`, by its `ttl`, or as long as
+/// the publisher asked us to wait, within the bounds
+fn fetch_interval(feed: &Feed, min_interval: u32, max_interval: u32) -> u32 {
+    if feed.has_websub_lease() {
+        // the hub pushes updates, polling is only a fallback
+        return max_interval;
+    }
+    let ttl = feed.ttl.map(|ttl| ttl * 60).unwrap_or_default();
+    let requested = feed
+        .next_fetch
+        .and_then(|t| t.duration_since(SystemTime::now()).ok())
+        .map_or(0, |wait| u32::try_from(wait.as_secs()).unwrap_or(u32::MAX));
+    cmp::min(
+        cmp::max(cmp::max(ttl, requested), min_interval),
+        max_interval,
+    )
+}
+
 async fn fetch_and_push_updates(
     bot: Bot,
     db: Arc<Mutex<Database>>,
-    feed: Feed,
+    mut feed: Feed,
 ) -> Result<(), tbot::errors::MethodCall> {
-    let new_feed = match pull_feed(&feed.link).await {
-        Ok(feed) => feed,
+    let fetched = pull_feed_if_modified(&feed.link, &feed.validators, &feed.request).await;
+    let wait = match &fetched {
+        Ok(fetched) => fetched.max_age,
+        Err(e) => e.retry_after(),
+    };
+    db.lock()
+        .unwrap()
+        .set_next_fetch(&feed.link, wait.map(|wait| SystemTime::now() + wait));
+    if let Ok(fetched) = &fetched {
+        let new_link = {
+            let mut db = db.lock().unwrap();
+            db.set_gone(&feed.link, false);
+            db.track_redirect(&feed.link, fetched.moved_to.as_deref())
+        };
+        if let Some(new_link) = new_link {
+            let msg = tr!(
+                "feed_moved",
+                link = Escape(&feed.link),
+                title = Escape(&feed.title),
+                new_link = Escape(&new_link)
+            );
+            push_info_updates(&bot, &db, &feed, parameters::Text::html(&msg)).await?;
+            let moved = db.lock().unwrap().move_feed(&feed.link, &new_link);
+            let owner = moved.and_then(|moved| moved.options_dropped_for);
+            // merged into the feed of the new link if it was subscribed too
+            let moved_feed = db.lock().unwrap().get_feed(&new_link);
+            feed = match moved_feed {
+                Some(feed) => feed,
+                None => return Ok(()),
+            };
+            if let Some(owner) = owner {
+                let msg = tr!(
+                    "feed_options_dropped",
+                    link = Escape(&feed.link),
+                    title = Escape(&feed.title)
+                );
+                let settings = db.lock().unwrap().get_setting(owner, &feed.link);
+                if let Some(settings) = settings {
+                    let msg = Message::Text(parameters::Text::html(&msg));
+                    push_message(&bot, &db, owner, &settings, msg).await?;
+                }
+            }
+        }
+    }
+    let (mut new_feed, validators) = match fetched.map(|fetched| fetched.feed) {
+        Ok(Some(fetched)) => fetched,
+        Ok(None) => {
+            // nothing to parse, but the feed is up
+            db.lock().unwrap().reset_down_time(&feed.link);
+            return Ok(());
+        }
         Err(e) => {
             let down_time = db.lock().unwrap().get_or_update_down_time(&feed.link);
             if down_time.is_none() {
                 // user unsubscribed while fetching the feed
                 return Ok(());
             }
+            // told once, until it's back
+            let is_gone = matches!(e, FeedError::Status(StatusCode::GONE, _));
+            if is_gone && db.lock().unwrap().set_gone(&feed.link, true) {
+                let msg = tr!(
+                    "feed_gone",
+                    link = Escape(&feed.link),
+                    title = Escape(&feed.title)
+                );
+                push_info_updates(&bot, &db, &feed, parameters::Text::html(&msg)).await?;
+            }
             // 5 days
             if down_time.unwrap().as_secs() > 5 * 24 * 60 * 60 {
                 db.lock().unwrap().reset_down_time(&feed.link);
@@ -89,11 +174,31 @@ async fn fetch_and_push_updates(
         }
     };
 
-    let updates = db.lock().unwrap().update(&feed.link, new_feed);
+    if Watch::from_link(&feed.link).map_or(false, |watch| watch.diff) {
+        let snapshot = new_feed.items.first().and_then(|item| item.content.clone());
+        let previous = db.lock().unwrap().swap_snapshot(&feed.link, snapshot);
+        crate::watch::diff_items(&mut new_feed, previous.as_deref());
+    }
+    crate::websub::subscribe_if_needed(&db, &feed.link, &new_feed).await;
+    let updates = {
+        let mut db = db.lock().unwrap();
+        db.set_validators(&feed.link, validators);
+        db.update(&feed.link, new_feed)
+    };
+    push_updates(&bot, &db, &feed, updates).await
+}
+
+pub async fn push_updates(
+    bot: &Bot,
+    db: &Arc<Mutex<Database>>,
+    feed: &Feed,
+    updates: Vec<FeedUpdate>,
+) -> Result<(), tbot::errors::MethodCall> {
     for update in updates {
         match update {
-            FeedUpdate::Items(items) => {
-                push_rss_updates(&bot, &db, &feed, &items).await?;
+            FeedUpdate::Items(mut items) => {
+                sort_oldest_first(&mut items);
+                push_rss_updates(bot, db, feed, &items).await?;
             }
             FeedUpdate::Title(new_title) => {
                 let msg = tr!(
@@ -102,18 +207,112 @@ async fn fetch_and_push_updates(
                     title = Escape(&feed.title),
                     new_title = Escape(&new_title)
                 );
-                push_info_updates(&bot, &db, &feed, parameters::Text::html(&msg)).await?;
+                push_info_updates(bot, db, feed, parameters::Text::html(&msg)).await?;
+            }
+            FeedUpdate::ItemEdited(item, messages) => {
+                edit_messages(bot, db, feed, &item, &messages).await;
+            }
+            FeedUpdate::ItemRemoved(messages) => {
+                for message in messages {
+                    if edit_settings(db, feed, message.chat).is_none() {
+                        continue;
+                    }
+                    // bots can't delete messages older than 48 hours
+                    let _ignore_result = bot
+                        .delete_message(
+                            tbot::types::chat::Id(message.chat),
+                            tbot::types::message::Id(message.id),
+                        )
+                        .call()
+                        .await;
+                }
             }
         }
     }
     Ok(())
 }
 
+/// Feeds list the newest items first, those with dates are sorted by them
+/// among themselves, the others keep their places
+fn sort_oldest_first(items: &mut [feed::Item]) {
+    items.reverse();
+    let dated: Vec<usize> = (0..items.len())
+        .filter(|&i| items[i].published.is_some())
+        .collect();
+    let mut sorted: Vec<feed::Item> = dated.iter().map(|&i| items[i].clone()).collect();
+    sorted.sort_by_key(|item| item.published);
+    for (i, item) in dated.into_iter().zip(sorted) {
+        items[i] = item;
+    }
+}
+
+/// Show the new version of an item in the messages it was sent as
+async fn edit_messages(
+    bot: &Bot,
+    db: &Arc<Mutex<Database>>,
+    feed: &Feed,
+    item: &feed::Item,
+    messages: &[SentMessage],
+) {
+    for message in messages {
+        let settings = match edit_settings(db, feed, message.chat) {
+            Some(settings) => settings,
+            None => continue,
+        };
+        let chat_id = tbot::types::chat::Id(message.chat);
+        let message_id = tbot::types::message::Id(message.id);
+        let mut text = format_item_msg(feed, &settings, item);
+        // errors like "message is not modified" don't matter
+        let _ignore_result = if message.caption {
+            if text_len(&text) > TELEGRAM_MAX_CAPTION_LEN {
+                text = format_item_link(feed, &settings, item);
+            }
+            bot.edit_message_caption(chat_id, message_id, parameters::Text::html(&text))
+                .call()
+                .await
+        } else {
+            let mut edit =
+                bot.edit_message_text(chat_id, message_id, parameters::Text::html(&text));
+            if settings.disable_preview.unwrap() {
+                edit = edit.web_page_preview(WebPagePreviewState::Disabled)
+            }
+            edit.call().await
+        };
+    }
+}
+
+/// Settings of a subscriber who still wants the messages of a feed edited
+fn edit_settings(
+    db: &Arc<Mutex<Database>>,
+    feed: &Feed,
+    subscriber: SubscriberId,
+) -> Option<FeedSettings> {
+    let mut db = db.lock().unwrap();
+    if !db.is_subscribed(subscriber, &feed.link) {
+        return None;
+    }
+    db.get_setting(subscriber, &feed.link)
+        .filter(|settings| settings.edit_msg.unwrap())
+}
+
+/// Remember where an item was sent, for the subscribers who want edits
+fn record_message(
+    db: &Arc<Mutex<Database>>,
+    feed: &Feed,
+    settings: &FeedSettings,
+    item: &feed::Item,
+    sent: Option<SentMessage>,
+) {
+    if let (true, Some(sent)) = (settings.edit_msg.unwrap(), sent) {
+        db.lock().unwrap().record_message(&feed.link, item, sent);
+    }
+}
+
 async fn push_rss_updates(
     bot: &Bot,
     db: &Arc<Mutex<Database>>,
     feed: &Feed,
-    items: &Vec<feed::Item>,
+    items: &[feed::Item],
 ) -> Result<(), tbot::errors::MethodCall> {
     for subscriber in feed.subscribers.iter().copied() {
         let settings = db
@@ -122,47 +321,199 @@ async fn push_rss_updates(
             .get_setting(subscriber, &feed.link)
             .unwrap();
 
-        let head = if settings.hide_rss_title.unwrap() {
-            String::new()
-        } else {
-            format!("<b>{}</b>", Escape(&feed.title))
-        };
+        let cutoff = settings.cutoff();
+        let items: Vec<&feed::Item> = items
+            .iter()
+            .filter(|item| match (item.published, cutoff) {
+                (Some(published), Some(cutoff)) => published >= cutoff,
+                _ => true,
+            })
+            .collect();
 
-        let format_rss_item = |item: &feed::Item| -> String {
-            let title = item
-                .title
-                .as_ref()
-                .map(|s| s.as_str())
-                .unwrap_or_else(|| &feed.title);
-            let link = item
-                .link
-                .as_ref()
-                .map(|s| s.as_str())
-                .unwrap_or_else(|| &feed.link);
-            if settings.link_only.unwrap() {
-                return format!("<a href=\"{}\">{}</a>", Escape(link), Escape(link));
+        if settings.send_media.unwrap() {
+            // Keep the order, only items between two media messages can be combined
+            let mut text_items = Vec::new();
+            for item in items {
+                match Media::of(item) {
+                    Some(media) => {
+                        push_text_items(bot, db, feed, subscriber, &settings, &text_items).await?;
+                        text_items.clear();
+                        push_media_item(bot, db, feed, subscriber, &settings, item, media).await?;
+                    }
+                    None => text_items.push(item),
+                }
             }
-            format!("<a href=\"{}\">{}</a>", Escape(link), Escape(title))
-        };
-
-        let msgs;
-        if settings.combine_msg.unwrap() {
-            msgs = format_large_msg(head, &items, format_rss_item);
+            push_text_items(bot, db, feed, subscriber, &settings, &text_items).await?;
         } else {
-            msgs = items
-                .iter()
-                .map(|item| String::new() + &head + "\n" + &format_rss_item(item))
-                .collect();
+            push_text_items(bot, db, feed, subscriber, &settings, &items).await?;
+        }
+    }
+    Ok(())
+}
+
+fn format_rss_head(feed: &Feed, settings: &FeedSettings) -> String {
+    if settings.hide_rss_title.unwrap() {
+        String::new()
+    } else {
+        format!("<b>{}</b>", Escape(&feed.title))
+    }
+}
+
+fn format_item_link(feed: &Feed, settings: &FeedSettings, item: &feed::Item) -> String {
+    let title = item
+        .title
+        .as_ref()
+        .map(|s| s.as_str())
+        .unwrap_or_else(|| &feed.title);
+    let link = item
+        .link
+        .as_ref()
+        .map(|s| s.as_str())
+        .unwrap_or_else(|| &feed.link);
+    if settings.link_only.unwrap() {
+        format!("<a href=\"{}\">{}</a>", Escape(link), Escape(link))
+    } else {
+        format!("<a href=\"{}\">{}</a>", Escape(link), Escape(title))
+    }
+}
+
+/// A message of a single item
+fn format_item_msg(feed: &Feed, settings: &FeedSettings, item: &feed::Item) -> String {
+    String::new() + &format_rss_head(feed, settings) + "\n" + &format_rss_item(feed, settings, item)
+}
+
+fn format_rss_item(feed: &Feed, settings: &FeedSettings, item: &feed::Item) -> String {
+    let mut line = format_item_link(feed, settings, item);
+    if settings.excerpt.unwrap() {
+        if let Some(excerpt) = item.excerpt(EXCERPT_MAX_LEN) {
+            line.push('\n');
+            line.push_str(&Escape(&excerpt).to_string());
+        }
+    }
+    if settings.hashtags.unwrap() {
+        let mut hashtags: Vec<String> = Vec::new();
+        for hashtag in item.categories.iter().filter_map(|c| format_hashtag(c)) {
+            if !hashtags.contains(&hashtag) {
+                hashtags.push(hashtag);
+            }
         }
+        if !hashtags.is_empty() {
+            line.push('\n');
+            line.push_str(&Escape(&hashtags.join(" ")).to_string());
+        }
+    }
+    line
+}
 
+async fn push_text_items(
+    bot: &Bot,
+    db: &Arc<Mutex<Database>>,
+    feed: &Feed,
+    subscriber: SubscriberId,
+    settings: &FeedSettings,
+    items: &[&feed::Item],
+) -> Result<(), tbot::errors::MethodCall> {
+    if items.is_empty() {
+        return Ok(());
+    }
+    // a combined message can't be edited for one of its items
+    if settings.combine_msg.unwrap() && !settings.edit_msg.unwrap() {
+        let head = format_rss_head(feed, settings);
+        let msgs = format_large_msg(head, items, |item| format_rss_item(feed, settings, item));
         for msg in msgs {
             let formatted_msg = parameters::Text::html(&msg);
-            push_message(&bot, &db, subscriber, &settings, formatted_msg).await?;
+            push_message(bot, db, subscriber, settings, Message::Text(formatted_msg)).await?;
+        }
+    } else {
+        for item in items {
+            let msg = format_item_msg(feed, settings, item);
+            let formatted_msg = parameters::Text::html(&msg);
+            let sent =
+                push_message(bot, db, subscriber, settings, Message::Text(formatted_msg)).await?;
+            record_message(db, feed, settings, item, sent);
         }
     }
     Ok(())
 }
 
+enum Media<'a> {
+    Audio(&'a feed::Attachment),
+    Photos(Vec<&'a feed::Attachment>),
+    Document(&'a feed::Attachment),
+}
+
+impl<'a> Media<'a> {
+    /// Audio is preferred, then images, anything else is sent as a document
+    fn of(item: &'a feed::Item) -> Option<Self> {
+        use feed::AttachmentKind;
+        let attachments = &item.attachments;
+        if let Some(audio) = attachments.iter().find(|a| a.kind == AttachmentKind::Audio) {
+            return Some(Media::Audio(audio));
+        }
+        let photos: Vec<_> = attachments
+            .iter()
+            .filter(|a| a.kind == AttachmentKind::Image)
+            .take(TELEGRAM_MAX_ALBUM_LEN)
+            .collect();
+        if !photos.is_empty() {
+            return Some(Media::Photos(photos));
+        }
+        attachments.first().map(Media::Document)
+    }
+}
+
+async fn push_media_item(
+    bot: &Bot,
+    db: &Arc<Mutex<Database>>,
+    feed: &Feed,
+    subscriber: SubscriberId,
+    settings: &FeedSettings,
+    item: &feed::Item,
+    media: Media<'_>,
+) -> Result<(), tbot::errors::MethodCall> {
+    let mut caption = format_item_msg(feed, settings, item);
+    if text_len(&caption) > TELEGRAM_MAX_CAPTION_LEN {
+        caption = format_item_link(feed, settings, item);
+    }
+    let caption = parameters::Text::html(&caption);
+
+    let album: Vec<GroupMedia>;
+    let msg = match media {
+        Media::Audio(audio) => Message::Audio(input_file::Audio::url(&audio.url).caption(caption)),
+        Media::Photos(photos) if photos.len() == 1 => {
+            Message::Photo(input_file::Photo::url(&photos[0].url).caption(caption))
+        }
+        Media::Photos(photos) => {
+            album = photos
+                .iter()
+                .enumerate()
+                .map(|(i, photo)| {
+                    let photo = input_file::Photo::url(&photo.url);
+                    // the caption of the first photo is shown as the caption of album
+                    GroupMedia::Photo(if i == 0 {
+                        photo.caption(caption)
+                    } else {
+                        photo
+                    })
+                })
+                .collect();
+            Message::Album(&album)
+        }
+        Media::Document(document) => {
+            Message::Document(input_file::Document::url(&document.url).caption(caption))
+        }
+    };
+    let sent = match push_message(bot, db, subscriber, settings, msg).await {
+        Err(tbot::errors::MethodCall::RequestError { .. }) => {
+            // Telegram can't fetch the file, fallback to a text message
+            push_message(bot, db, subscriber, settings, Message::Text(caption)).await?
+        }
+        other => other?,
+    };
+    record_message(db, feed, settings, item, sent);
+    Ok(())
+}
+
 async fn push_info_updates(
     bot: &Bot,
     db: &Arc<Mutex<Database>>,
@@ -175,25 +526,62 @@ async fn push_info_updates(
             .unwrap()
             .get_setting(subscriber, &feed.link)
             .unwrap();
-        push_message(&bot, &db, subscriber, &settings, msg).await?;
+        push_message(&bot, &db, subscriber, &settings, Message::Text(msg)).await?;
     }
     Ok(())
 }
 
+#[derive(Clone, Copy)]
+enum Message<'a> {
+    Text(parameters::Text<'a>),
+    Audio(input_file::Audio<'a>),
+    Photo(input_file::Photo<'a>),
+    Document(input_file::Document<'a>),
+    Album(&'a [GroupMedia<'a>]),
+}
+
+/// `None` if nothing is sent, like when the chat is gone
 async fn push_message(
     bot: &Bot,
     db: &Arc<Mutex<Database>>,
     mut subscriber: SubscriberId,
     settings: &FeedSettings,
-    msg: parameters::Text<'_>,
-) -> Result<(), tbot::errors::MethodCall> {
+    msg: Message<'_>,
+) -> Result<Option<SentMessage>, tbot::errors::MethodCall> {
     use tbot::errors::MethodCall;
     'retry: for _ in 0..3 {
-        let mut bot_msg = bot.send_message(tbot::types::chat::Id(subscriber), msg);
-        if settings.disable_preview.unwrap() {
-            bot_msg = bot_msg.web_page_preview(WebPagePreviewState::Disabled)
-        }
-        match bot_msg.call().await {
+        let chat_id = tbot::types::chat::Id(subscriber);
+        let result = match msg {
+            Message::Text(text) => {
+                let mut bot_msg = bot.send_message(chat_id, text);
+                if settings.disable_preview.unwrap() {
+                    bot_msg = bot_msg.web_page_preview(WebPagePreviewState::Disabled)
+                }
+                bot_msg.call().await.map(|msg| Some(msg.id))
+            }
+            Message::Audio(audio) => bot
+                .send_audio(chat_id, audio)
+                .call()
+                .await
+                .map(|msg| Some(msg.id)),
+            Message::Photo(photo) => bot
+                .send_photo(chat_id, photo)
+                .call()
+                .await
+                .map(|msg| Some(msg.id)),
+            Message::Document(document) => bot
+                .send_document(chat_id, document)
+                .call()
+                .await
+                .map(|msg| Some(msg.id)),
+            // the caption is on the first one
+            Message::Album(album) => bot
+                .send_media_group(chat_id, album)
+                .call()
+                .await
+                .map(|msgs| msgs.first().map(|msg| msg.id)),
+        };
+        match result {
             Err(MethodCall::RequestError { description, .. })
                 if chat_is_unavailable(&description) =>
             {
@@ -217,12 +605,16 @@ async fn push_message(
                 continue 'retry;
             }
             other => {
-                other?;
+                return Ok(other?.map(|id| SentMessage {
+                    chat: subscriber,
+                    id: id.0,
+                    caption: !matches!(msg, Message::Text(_)),
+                }));
             }
         }
         break 'retry;
     }
-    Ok(())
+    Ok(None)
 }
 
 pub fn chat_is_unavailable(s: &str) -> bool {
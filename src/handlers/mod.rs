@@ -1,3 +1,4 @@
+use std::str::ParseBoolError;
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -12,10 +13,16 @@ use tbot::{
     Bot,
 };
 
-use crate::client::pull_feed;
+use crate::client::{
+    check_certificates, fetch_feed, parse_header, probe_feeds, pull_feed, validate_feed,
+    CertificateError, FeedError, InvalidHeader, DIRECT,
+};
 use crate::constant::GLOBAL_ADMIN;
-use crate::data::Database;
+use crate::data::{Auth, Database, InvalidAuth, RequestOptions, UnknownDedupKey};
+use crate::feed::validate::{Diagnostic, Severity};
 use crate::messages::{format_large_msg, Escape};
+use crate::proxy::{parse_proxy, ProxyError};
+use crate::watch::Watch;
 
 mod opml;
 
@@ -111,16 +118,63 @@ pub async fn showset(
         return Ok(());
     }
     let setting = setting_wraped.unwrap();
+    let feed = db.lock().unwrap().get_feed(&feed_url).unwrap();
+    let dedup = feed.dedup;
+    // only what's set, the values may be secrets
+    let auth = feed.request.auth.as_ref().map_or("none", Auth::scheme);
+    let headers = feed
+        .request
+        .headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let proxy = feed
+        .request
+        .proxy
+        .as_deref()
+        .map(|proxy| match url::Url::parse(proxy) {
+            // without the credentials
+            Ok(url) => format!("{}://{}", url.scheme(), &url[url::Position::BeforeHost..]),
+            Err(_) => proxy.to_owned(),
+        })
+        .unwrap_or_default();
 
     let msg = format!(
         "disable_preview: {} \n\
          link_only: {} \n\
          hide_rss_title: {} \n\
-         combine_msg: {}",
+         combine_msg: {} \n\
+         excerpt: {} \n\
+         send_media: {} \n\
+         hashtags: {} \n\
+         max_age: {} \n\
+         edit_msg: {} \n\
+         dedup: {} \n\
+         strip_tracking: {} \n\
+         auth: {} \n\
+         header: {} \n\
+         user_agent: {} \n\
+         proxy: {} \n\
+         ca_cert: {} \n\
+         client_cert: {}",
         Escape(&setting.disable_preview.unwrap().to_string()),
         Escape(&setting.link_only.unwrap().to_string()),
         Escape(&setting.hide_rss_title.unwrap().to_string()),
         Escape(&setting.combine_msg.unwrap().to_string()),
+        Escape(&setting.excerpt.unwrap().to_string()),
+        Escape(&setting.send_media.unwrap().to_string()),
+        Escape(&setting.hashtags.unwrap().to_string()),
+        Escape(&setting.max_age.unwrap().to_string()),
+        Escape(&setting.edit_msg.unwrap().to_string()),
+        Escape(&dedup.key.to_string()),
+        Escape(&dedup.strip_tracking.to_string()),
+        auth,
+        headers,
+        feed.request.user_agent.as_deref().unwrap_or_default(),
+        proxy,
+        certificate_count(feed.request.ca_cert.as_deref()),
+        certificate_count(feed.request.client_cert.as_deref()),
     );
 
     update_response(&cmd.bot, target, parameters::Text::plain(&msg)).await?;
@@ -135,60 +189,67 @@ pub async fn set(
     let chat_id = cmd.chat.id;
     let chat_id_str = cmd.chat.id.to_string();
     let text = &cmd.text.value;
-    let args = text.split_whitespace().collect::<Vec<_>>();
     let mut target_id = chat_id;
     let target = &mut MsgTarget::new(chat_id, cmd.message_id);
     let feed_url;
-    let setting_key_value;
+    let setting_key;
+    let setting_value;
 
-    match &*args {
-        [url, kv] => {
+    let args = split_setting_args(text);
+    match args
+        .as_ref()
+        .map(|(args, key, value)| (&**args, *key, *value))
+    {
+        Some(([url], key, value)) => {
             let user_id = cmd.from.as_ref().unwrap().id;
             let result = check_op_permission(&cmd.bot, &chat_id_str, target, user_id).await?;
             if result.is_none() {
                 return Ok(());
             }
-            feed_url = url;
-            setting_key_value = *kv;
+            feed_url = *url;
+            setting_key = key;
+            setting_value = value;
         }
-        [channel, url, kv] => {
+        Some(([channel, url], key, value)) => {
             let user_id = cmd.from.as_ref().unwrap().id;
             let channel_id = check_op_permission(&cmd.bot, channel, target, user_id).await?;
             if channel_id.is_none() {
                 return Ok(());
             }
             target_id = channel_id.unwrap();
-            feed_url = url;
-            setting_key_value = *kv;
-        }
-        [..] => {
-            let msg = "使用方法: /set [Channel ID] <RSS URL> <key=value>";
-            update_response(&cmd.bot, target, parameters::Text::plain(&msg)).await?;
-            return Ok(());
-        }
-    };
-
-    let setting_key_value_arr = setting_key_value.split("=").collect::<Vec<_>>();
-    let setting_key;
-    let setting_value;
-    match *setting_key_value_arr {
-        [key, value] => {
+            feed_url = *url;
             setting_key = key;
             setting_value = value;
         }
-        [..] => {
+        _ => {
             let msg = "使用方法: /set [Channel ID] <RSS URL> <key=value>";
             update_response(&cmd.bot, target, parameters::Text::plain(&msg)).await?;
             return Ok(());
         }
-    }
+    };
 
+    if let "auth" | "header" | "user_agent" | "proxy" | "ca_cert" | "client_cert" | "client_key" =
+        setting_key
+    {
+        let msg = if cmd.chat.kind.is_private() {
+            set_request_option(&db, target_id.0, &feed_url, setting_key, setting_value)
+        } else {
+            "请在私聊中设置".into()
+        };
+        update_response(&cmd.bot, target, parameters::Text::plain(&msg)).await?;
+        return Ok(());
+    }
     let setting_wraped = db.lock().unwrap().get_setting(target_id.0, &feed_url);
     if setting_wraped.is_none() {
         let msg = "找不到该订阅";
         update_response(&cmd.bot, target, parameters::Text::plain(&msg)).await?;
         return Ok(());
     }
+    if let "dedup" | "strip_tracking" = setting_key {
+        let msg = set_dedup(&db, &feed_url, setting_key, setting_value).await;
+        update_response(&cmd.bot, target, parameters::Text::plain(&msg)).await?;
+        return Ok(());
+    }
     let mut setting = setting_wraped.unwrap();
     let mut err = None;
     match setting_key {
@@ -220,6 +281,41 @@ pub async fn set(
                 Err(e) => err = Some(e.to_string()),
             }
         }
+        "edit_msg" => {
+            let setting_value_parsed = setting_value.parse::<bool>();
+            match setting_value_parsed {
+                Ok(v) => setting.edit_msg = Some(v),
+                Err(e) => err = Some(e.to_string()),
+            }
+        }
+        "excerpt" => {
+            let setting_value_parsed = setting_value.parse::<bool>();
+            match setting_value_parsed {
+                Ok(v) => setting.excerpt = Some(v),
+                Err(e) => err = Some(e.to_string()),
+            }
+        }
+        "send_media" => {
+            let setting_value_parsed = setting_value.parse::<bool>();
+            match setting_value_parsed {
+                Ok(v) => setting.send_media = Some(v),
+                Err(e) => err = Some(e.to_string()),
+            }
+        }
+        "hashtags" => {
+            let setting_value_parsed = setting_value.parse::<bool>();
+            match setting_value_parsed {
+                Ok(v) => setting.hashtags = Some(v),
+                Err(e) => err = Some(e.to_string()),
+            }
+        }
+        "max_age" => {
+            let setting_value_parsed = setting_value.parse::<u32>();
+            match setting_value_parsed {
+                Ok(v) => setting.max_age = Some(v),
+                Err(e) => err = Some(e.to_string()),
+            }
+        }
         _ => {
             let msg = "没有此设置项";
             update_response(&cmd.bot, target, parameters::Text::plain(&msg)).await?;
@@ -246,6 +342,117 @@ pub async fn set(
     Ok(())
 }
 
+/// Split `[Channel ID] <RSS URL> <key=value>`, the value is the rest of the text
+fn split_setting_args(text: &str) -> Option<(Vec<&str>, &str, &str)> {
+    let mut args = Vec::new();
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let end = rest.find(char::is_whitespace).unwrap_or_else(|| rest.len());
+        let arg = &rest[..end];
+        // unlike a URL, a key is all lowercase letters and `_`
+        if let Some(eq) = arg.find('=') {
+            let key = &arg[..eq];
+            if !key.is_empty() && key.chars().all(|c| c.is_ascii_lowercase() || c == '_') {
+                return Some((args, key, &rest[eq + 1..]));
+            }
+        }
+        args.push(arg);
+        rest = rest[end..].trim_start();
+    }
+    None
+}
+
+/// Certificates are shown by their number, the private key isn't shown at all
+fn certificate_count(pem: Option<&str>) -> usize {
+    pem.map_or(0, |pem| pem.matches("-----BEGIN CERTIFICATE-----").count())
+}
+
+/// Request options may be set before subscribing, a private feed can't be fetched without them
+fn set_request_option(
+    db: &Arc<Mutex<Database>>,
+    subscriber: i64,
+    feed_url: &str,
+    key: &str,
+    value: &str,
+) -> String {
+    let mut db = db.lock().unwrap();
+    let mut options = db.request_options(subscriber, feed_url);
+    let value = value.trim();
+    let parsed = match key {
+        "auth" if value.is_empty() || value == "none" => {
+            options.auth = None;
+            Ok(())
+        }
+        "auth" => value
+            .parse()
+            .map(|v| options.auth = Some(v))
+            .map_err(|e: InvalidAuth| e.to_string()),
+        "header" => parse_header(value)
+            .map(|(name, value)| {
+                options.headers.retain(|(n, _)| *n != name);
+                if let Some(value) = value {
+                    options.headers.push((name, value));
+                }
+            })
+            .map_err(|e: InvalidHeader| e.to_string()),
+        "proxy" if value.is_empty() || value == DIRECT => {
+            options.proxy = Some(value.to_owned()).filter(|v| !v.is_empty());
+            Ok(())
+        }
+        "proxy" => parse_proxy(value)
+            .map(|v| options.proxy = Some(v.into_string()))
+            .map_err(|e: ProxyError| e.to_string()),
+        "ca_cert" | "client_cert" | "client_key" => {
+            let pem = Some(value.to_owned()).filter(|v| !v.is_empty() && v != "none");
+            match key {
+                "ca_cert" => options.ca_cert = pem,
+                "client_cert" => options.client_cert = pem,
+                _ => options.client_key = pem,
+            }
+            check_certificates(&options).map_err(|e: CertificateError| e.to_string())
+        }
+        _ => {
+            options.user_agent = Some(value.to_owned()).filter(|v| !v.is_empty());
+            Ok(())
+        }
+    };
+    if let Err(e) = parsed {
+        return tr!("request_option_invalid", error = e);
+    }
+    if !db.set_request_options(subscriber, feed_url, options) {
+        return tr!("request_options_shared").into();
+    }
+    tr!("request_options_changed").into()
+}
+
+/// Dedup settings belong to the feed, and the current items are needed to change them
+async fn set_dedup(db: &Arc<Mutex<Database>>, feed_url: &str, key: &str, value: &str) -> String {
+    let feed = match db.lock().unwrap().get_feed(feed_url) {
+        Some(feed) => feed,
+        None => return "找不到该订阅".into(),
+    };
+    let mut dedup = feed.dedup;
+    let parsed = if key == "dedup" {
+        value
+            .parse()
+            .map(|v| dedup.key = v)
+            .map_err(|e: UnknownDedupKey| e.to_string())
+    } else {
+        value
+            .parse()
+            .map(|v| dedup.strip_tracking = v)
+            .map_err(|e: ParseBoolError| e.to_string())
+    };
+    if let Err(e) = parsed {
+        return format!("设置值错误 ({})", e);
+    }
+    match pull_feed(feed_url, &feed.request).await {
+        Ok(feed) if db.lock().unwrap().set_dedup(feed_url, dedup, &feed.items) => "更改完成".into(),
+        Ok(_) => "更改失败".into(),
+        Err(e) => format!("更改失败 ({})", e.to_user_friendly()),
+    }
+}
+
 pub async fn rss(
     db: Arc<Mutex<Database>>,
     cmd: Arc<Command<Text>>,
@@ -284,11 +491,16 @@ pub async fn rss(
                 .collect::<Vec<Either<char, &str>>>()
         });
         format_large_msg(tr!("subscription_list").to_string(), &feeds, |feed| {
-            format!(
+            let mut line = format!(
                 "<a href=\"{}\">{}</a>",
                 Escape(&feed.link),
                 Escape(&feed.title)
-            )
+            );
+            if let Some(warning) = &feed.warning {
+                line.push('\n');
+                line.push_str(&tr!("feed_partly_read", error = Escape(warning)));
+            }
+            line
         })
     } else {
         vec![tr!("subscription_list_empty").to_string()]
@@ -319,33 +531,23 @@ pub async fn sub(
     let args = text.split_whitespace().collect::<Vec<_>>();
     let mut target_id = chat_id;
     let target = &mut MsgTarget::new(chat_id, cmd.message_id);
-    let feed_url;
-
-    match &*args {
-        [url] => {
-            let user_id = cmd.from.as_ref().unwrap().id;
-            let result = check_op_permission(&cmd.bot, &chat_id_str, target, user_id).await?;
-            if result.is_none() {
-                return Ok(());
-            }
-            feed_url = url
-        }
-        [channel, url] => {
-            let user_id = cmd.from.as_ref().unwrap().id;
-            let channel_id = check_op_permission(&cmd.bot, channel, target, user_id).await?;
-            if channel_id.is_none() {
-                return Ok(());
-            }
-            target_id = channel_id.unwrap();
-            feed_url = url;
-        }
-        [..] => {
+    let (channel, mut feed_url) = match parse_feed_args(&args) {
+        Some(args) => args,
+        None => {
             let msg = tr!("sub_how_to_use");
             update_response(&cmd.bot, target, parameters::Text::plain(&msg)).await?;
             return Ok(());
         }
     };
-    if db.lock().unwrap().is_subscribed(target_id.0, feed_url) {
+    let user_id = cmd.from.as_ref().unwrap().id;
+    let chat = channel.unwrap_or(&chat_id_str);
+    let permitted = check_op_permission(&cmd.bot, chat, target, user_id).await?;
+    match (channel, permitted) {
+        (_, None) => return Ok(()),
+        (Some(_), Some(channel_id)) => target_id = channel_id,
+        (None, Some(_)) => (),
+    }
+    if db.lock().unwrap().is_subscribed(target_id.0, &feed_url) {
         update_response(
             &cmd.bot,
             target,
@@ -366,9 +568,54 @@ pub async fn sub(
         parameters::Text::plain(tr!("processing_please_wait")),
     )
     .await?;
-    let msg = match pull_feed(feed_url).await {
+    let options = db.lock().unwrap().request_options(target_id.0, &feed_url);
+    let mut result = pull_feed(&feed_url, &options).await;
+    if let Err(FeedError::Html(links)) = &result {
+        let links = if links.is_empty() {
+            probe_feeds(&feed_url, &options).await
+        } else {
+            links.clone()
+        };
+        match &*links {
+            [] => (),
+            [link] => {
+                feed_url = link.clone();
+                if db.lock().unwrap().is_subscribed(target_id.0, &feed_url) {
+                    update_response(
+                        &cmd.bot,
+                        target,
+                        parameters::Text::plain(tr!("subscribed_to_rss")),
+                    )
+                    .await?;
+                    return Ok(());
+                }
+                result = pull_feed(&feed_url, &options).await;
+            }
+            _ => {
+                // keep the channel argument, so the command can be copied as is
+                let channel = args[..args.len() - 1]
+                    .iter()
+                    .map(|arg| format!("{} ", arg))
+                    .collect::<String>();
+                let feeds = links
+                    .iter()
+                    .map(|link| format!("<code>/sub {}{}</code>", Escape(&channel), Escape(link)))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let msg = tr!("multiple_feeds_found", feeds = feeds);
+                update_response(&cmd.bot, target, parameters::Text::html(&msg)).await?;
+                return Ok(());
+            }
+        }
+    }
+    let msg = match result {
         Ok(feed) => {
-            if db.lock().unwrap().subscribe(target_id.0, feed_url, &feed) {
+            let mut db = db.lock().unwrap();
+            if options != RequestOptions::default() {
+                // they may be set for the page the feed is found in
+                db.set_request_options(target_id.0, &feed_url, options);
+            }
+            if db.subscribe(target_id.0, &feed_url, &feed) {
                 tr!(
                     "subscription_succeeded",
                     link = Escape(&feed.link),
@@ -394,33 +641,29 @@ pub async fn unsub(
     let args = text.split_whitespace().collect::<Vec<_>>();
     let mut target_id = chat_id;
     let target = &mut MsgTarget::new(chat_id, cmd.message_id);
-    let feed_url;
-
-    match &*args {
-        [url] => {
-            let user_id = cmd.from.as_ref().unwrap().id;
-            let result = check_op_permission(&cmd.bot, &chat_id_str, target, user_id).await?;
-            if result.is_none() {
-                return Ok(());
-            }
-            feed_url = url
-        }
-        [channel, url] => {
-            let user_id = cmd.from.as_ref().unwrap().id;
-            let channel_id = check_op_permission(&cmd.bot, channel, target, user_id).await?;
-            if channel_id.is_none() {
-                return Ok(());
-            }
-            target_id = channel_id.unwrap();
-            feed_url = url;
-        }
-        [..] => {
+    let (channel, feed_url) = match parse_feed_args(&args) {
+        Some(args) => args,
+        None => {
             let msg = tr!("unsub_how_to_use");
             update_response(&cmd.bot, target, parameters::Text::plain(&msg)).await?;
             return Ok(());
         }
     };
-    let msg = if let Some(feed) = db.lock().unwrap().unsubscribe(target_id.0, feed_url) {
+    let user_id = cmd.from.as_ref().unwrap().id;
+    let chat = channel.unwrap_or(&chat_id_str);
+    let permitted = check_op_permission(&cmd.bot, chat, target, user_id).await?;
+    match (channel, permitted) {
+        (_, None) => return Ok(()),
+        (Some(_), Some(channel_id)) => target_id = channel_id,
+        (None, Some(_)) => (),
+    }
+    let feed = db.lock().unwrap().unsubscribe(target_id.0, &feed_url);
+    let msg = if let Some(feed) = feed {
+        if feed.subscribers.is_empty() {
+            if let Some(hub) = &feed.hub {
+                crate::websub::unsubscribe(hub).await;
+            }
+        }
         tr!(
             "unsubscription_succeeded",
             link = Escape(&feed.link),
@@ -433,6 +676,107 @@ pub async fn unsub(
     Ok(())
 }
 
+/// Split `[Channel ID] <URL> [[diff] <CSS selector>]` into the channel and the feed link
+fn parse_feed_args<'a>(args: &[&'a str]) -> Option<(Option<&'a str>, String)> {
+    let (channel, url, selector) = match args {
+        [] => return None,
+        [url] => (None, *url, &[][..]),
+        // the URL is told apart from a channel by its scheme
+        [url, selector @ ..] if url.contains("://") => (None, *url, selector),
+        [channel, url, selector @ ..] => (Some(*channel), *url, selector),
+    };
+    let link = if selector.is_empty() {
+        url.to_owned()
+    } else {
+        Watch::from_args(url, selector)?.link()
+    };
+    Some((channel, link))
+}
+
+pub async fn validate(
+    db: Arc<Mutex<Database>>,
+    cmd: Arc<Command<Text>>,
+) -> Result<(), tbot::errors::MethodCall> {
+    let chat_id = cmd.chat.id;
+    let args = cmd.text.value.split_whitespace().collect::<Vec<_>>();
+    let target = &mut MsgTarget::new(chat_id, cmd.message_id);
+    let feed_url = match &*args {
+        [url] => url,
+        _ => {
+            let msg = tr!("validate_how_to_use");
+            update_response(&cmd.bot, target, parameters::Text::plain(&msg)).await?;
+            return Ok(());
+        }
+    };
+    update_response(
+        &cmd.bot,
+        target,
+        parameters::Text::plain(tr!("processing_please_wait")),
+    )
+    .await?;
+
+    let options = db.lock().unwrap().request_options(chat_id.0, feed_url);
+    let result = match fetch_feed(feed_url, &options).await {
+        Ok(resp) => validate_feed(&resp.body, &resp.url, resp.content_type.as_ref()),
+        Err(e) => Err(e),
+    };
+    let msgs = match result {
+        Ok((feed, diagnostics)) => {
+            let count = |severity| {
+                diagnostics
+                    .iter()
+                    .filter(|d| d.severity() == severity)
+                    .count()
+            };
+            let head = tr!(
+                "validation_result",
+                title = Escape(&feed.title),
+                errors = count(Severity::Error),
+                warnings = count(Severity::Warning),
+                infos = count(Severity::Info)
+            );
+            format_large_msg(head, &diagnostics, format_diagnostic)
+        }
+        Err(e) => vec![tr!(
+            "validation_failed",
+            error = Escape(&e.to_user_friendly())
+        )],
+    };
+
+    let mut msgs = msgs.into_iter();
+    let first = msgs.next().expect("at least one message");
+    update_response(&cmd.bot, target, parameters::Text::html(&first)).await?;
+    for msg in msgs {
+        let msg = cmd
+            .bot
+            .send_message(chat_id, parameters::Text::html(&msg))
+            .reply_to_message_id(target.message_id)
+            .web_page_preview(WebPagePreviewState::Disabled)
+            .call()
+            .await?;
+        target.update(msg.id);
+    }
+    Ok(())
+}
+
+fn format_diagnostic(diagnostic: &Diagnostic) -> String {
+    let icon = match diagnostic.severity() {
+        Severity::Error => "❌",
+        Severity::Warning => "⚠️",
+        Severity::Info => "ℹ️",
+    };
+    let location = match diagnostic.item {
+        Some(index) => tr!("validate_item", index = index + 1),
+        None => tr!("validate_feed").into(),
+    };
+    format!(
+        "{} {}: {}",
+        icon,
+        location,
+        Escape(&diagnostic.problem.to_user_friendly())
+    )
+}
+
 pub async fn export(
     db: Arc<Mutex<Database>>,
     cmd: Arc<Command<Text>>,
@@ -2,14 +2,17 @@
 #![recursion_limit = "256"]
 
 use std::convert::TryInto;
-use std::env;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::panic;
 use std::path::PathBuf;
 use std::process;
 use std::sync::{Arc, Mutex}; // TODO: async Mutex
+use std::time::Duration;
 
 use anyhow::Context;
 use once_cell::sync::OnceCell;
+use reqwest::header::{HeaderValue, PROXY_AUTHORIZATION};
 use structopt::StructOpt;
 use tbot::{
     self,
@@ -28,8 +31,13 @@ mod fetcher;
 mod gardener;
 mod handlers;
 mod messages;
+mod proxy;
+mod watch;
+mod websub;
 
+use crate::client::ClientOptions;
 use crate::data::Database;
+use crate::proxy::Proxies;
 
 static BOT_NAME: OnceCell<String> = OnceCell::new();
 static BOT_ID: OnceCell<tbot::types::user::Id> = OnceCell::new();
@@ -72,12 +80,55 @@ struct Opt {
     #[structopt(long, value_name = "bytes", default_value = "2097152")]
     // default is 2MiB
     max_feed_size: u64,
+    /// Maximum number of items read from a feed, 0 is unlimited
+    #[structopt(long, value_name = "count", default_value = "500")]
+    max_feed_items: usize,
+    /// Timeout of feed requests
+    #[structopt(
+        long,
+        value_name = "seconds",
+        default_value = "10",
+        validator(check_interval)
+    )]
+    timeout: u32,
+    /// Retries of feed requests after network errors, with backoff
+    #[structopt(long, value_name = "count", default_value = "2")]
+    max_retries: u32,
     /// Single user mode, only specified user can use this bot
     #[structopt(long, value_name = "user id")]
     single_user: Option<i64>,
     /// DANGER: Insecure mode, accept invalid TLS certificates
     #[structopt(long)]
     insecure: bool,
+    /// Public URL of the WebSub callback server, enables WebSub
+    #[structopt(long, value_name = "url", parse(try_from_str = url::Url::parse))]
+    websub_url: Option<url::Url>,
+    /// Listen address of the WebSub callback server
+    #[structopt(long, value_name = "address", default_value = "127.0.0.1:8080")]
+    websub_listen: SocketAddr,
+    /// Proxy of all requests instead of the environment variables
+    #[structopt(long, value_name = "url", parse(try_from_str = proxy::parse_proxy))]
+    proxy: Option<url::Url>,
+    /// Local address of feed requests
+    #[structopt(long, value_name = "address")]
+    bind: Option<IpAddr>,
+    /// Fetch feeds only over IPv4 or IPv6
+    #[structopt(
+        long,
+        value_name = "version",
+        possible_values = &["4", "6"],
+        conflicts_with = "bind"
+    )]
+    ip_version: Option<u8>,
+    /// PEM file of root certificates trusted by feed requests, besides the built-in ones
+    #[structopt(long, value_name = "path", number_of_values = 1)]
+    ca_cert: Vec<PathBuf>,
+    /// PEM file of the client certificate of feed requests, with its private key
+    #[structopt(long, value_name = "path")]
+    client_cert: Option<PathBuf>,
+    /// PEM file of the private key, if it's not in the client certificate file
+    #[structopt(long, value_name = "path", requires = "client-cert")]
+    client_key: Option<PathBuf>,
 }
 
 fn check_interval(s: String) -> Result<(), String> {
@@ -111,7 +162,29 @@ async fn main() -> anyhow::Result<()> {
 
     let opt = Opt::from_args();
     let db = Arc::new(Mutex::new(Database::open(opt.database)?));
-    let bot = if let Some(proxy) = init_proxy() {
+    let proxies = Proxies::new(opt.proxy).context("Invalid proxy")?;
+    // binding to an unspecified address limits the connections to its version
+    let ip_version = opt.ip_version;
+    let local_address = opt.bind.or_else(|| match ip_version {
+        Some(4) => Some(Ipv4Addr::UNSPECIFIED.into()),
+        Some(6) => Some(Ipv6Addr::UNSPECIFIED.into()),
+        _ => None,
+    });
+    let ca_certs = opt
+        .ca_cert
+        .iter()
+        .map(fs::read)
+        .collect::<Result<_, _>>()
+        .context("Failed to read the root certificates")?;
+    let client_cert = opt
+        .client_cert
+        .iter()
+        .chain(&opt.client_key)
+        .map(fs::read)
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to read the client certificate")?;
+    let client_cert = Some(client_cert.join(&b'\n')).filter(|pem| !pem.is_empty());
+    let bot = if let Some(proxy) = init_proxy(&proxies).context("Invalid proxy")? {
         tbot::Bot::with_proxy(opt.token, proxy)
     } else {
         tbot::Bot::new(opt.token)
@@ -123,11 +196,30 @@ async fn main() -> anyhow::Result<()> {
         .context("Initialization failed, check your network and Telegram token")?;
 
     let bot_name = me.user.username.clone().unwrap();
-    crate::client::init_client(&bot_name, opt.insecure, opt.max_feed_size);
+    crate::client::init_client(
+        &bot_name,
+        ClientOptions {
+            insecure: opt.insecure,
+            max_feed_size: opt.max_feed_size,
+            max_feed_items: opt.max_feed_items,
+            timeout: Duration::from_secs(opt.timeout.into()),
+            max_retries: opt.max_retries,
+            proxies,
+            local_address,
+            ca_certs,
+            client_cert,
+        },
+    )
+    .context("Invalid TLS certificate")?;
 
     BOT_NAME.set(bot_name).unwrap();
     BOT_ID.set(me.user.id).unwrap();
 
+    if let Some(url) = opt.websub_url {
+        websub::start(bot.clone(), db.clone(), opt.websub_listen)
+            .context("Failed to start the WebSub callback server")?;
+        websub::init(url);
+    }
     gardener::start_pruning(bot.clone(), db.clone());
     fetcher::start(bot.clone(), db.clone(), opt.min_interval, opt.max_interval);
 
@@ -140,6 +232,7 @@ async fn main() -> anyhow::Result<()> {
     event_loop.command_if("rss", check_command, handle!(db, handlers::rss));
     event_loop.command_if("sub", check_command, handle!(db, handlers::sub));
     event_loop.command_if("unsub", check_command, handle!(db, handlers::unsub));
+    event_loop.command_if("validate", check_command, handle!(db, handlers::validate));
     event_loop.command_if("export", check_command, handle!(db, handlers::export));
     event_loop.command_if("set", check_command, handle!(db, handlers::set));
     event_loop.command_if("showset", check_command, handle!(db, handlers::showset));
@@ -157,17 +250,22 @@ fn enable_fail_fast() {
     }));
 }
 
-fn init_proxy() -> Option<Proxy> {
-    // Telegram Bot API only uses https, no need to check http_proxy
-    env::var("HTTPS_PROXY")
-        .or_else(|_| env::var("https_proxy"))
-        .map(|uri| {
-            let uri = uri
-                .try_into()
-                .unwrap_or_else(|e| panic!("Illegal HTTPS_PROXY: {}", e));
-            Proxy::new(Intercept::All, uri)
-        })
-        .ok()
+fn init_proxy(proxies: &Proxies) -> Result<Option<Proxy>, proxy::ProxyError> {
+    let api = url::Url::parse("https://api.telegram.org/").unwrap();
+    let (uri, authorization) = match proxies.for_url(&api) {
+        Some(proxy) => proxy::bot_api_proxy(proxy)?,
+        None => return Ok(None),
+    };
+    let uri = uri
+        .as_str()
+        .try_into()
+        .unwrap_or_else(|e| panic!("Illegal proxy: {}", e));
+    let mut proxy = Proxy::new(Intercept::All, uri);
+    if let Some(authorization) = authorization {
+        let value = HeaderValue::from_str(&authorization).unwrap();
+        proxy.set_header(PROXY_AUTHORIZATION, value);
+    }
+    Ok(Some(proxy))
 }
 
 fn print_error<E: std::error::Error>(err: E) {
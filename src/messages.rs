@@ -51,3 +51,73 @@ impl<'a> fmt::Display for Escape<'a> {
         Ok(())
     }
 }
+
+/// Turn a category into a Telegram hashtag, return `None` if nothing is left
+///
+/// Hashtags can only contain letters, digits and underscores,
+/// and Telegram ignores hashtags made of digits only
+pub fn format_hashtag(category: &str) -> Option<String> {
+    let mut tag = String::from("#");
+    for c in category.chars() {
+        if c.is_alphanumeric() {
+            tag.push(c);
+        } else if !tag.ends_with(|c| c == '#' || c == '_') {
+            tag.push('_');
+        }
+    }
+    let tag = tag.trim_end_matches('_');
+    if tag[1..].chars().all(|c| c.is_ascii_digit()) {
+        None
+    } else {
+        Some(tag.to_owned())
+    }
+}
+
+/// Length of a HTML message as Telegram counts it, in UTF-16 code units
+/// of the text left once the tags and entities are parsed
+pub fn text_len(html: &str) -> usize {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => (),
+        }
+    }
+    crate::feed::decode_html_entities(&text)
+        .encode_utf16()
+        .count()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hashtags() {
+        assert_eq!(format_hashtag("rust"), Some("#rust".into()));
+        assert_eq!(
+            format_hashtag("Rust Lang / 2020"),
+            Some("#Rust_Lang_2020".into())
+        );
+        assert_eq!(format_hashtag(" -C++- "), Some("#C".into()));
+        assert_eq!(format_hashtag("新闻"), Some("#新闻".into()));
+        assert_eq!(format_hashtag("2020"), None);
+        assert_eq!(format_hashtag("!!"), None);
+    }
+
+    #[test]
+    fn text_lengths() {
+        assert_eq!(text_len("<b>title</b>"), 5);
+        assert_eq!(
+            text_len(r#"<a href="https://example.com/?a=1&amp;b=2">link</a>"#),
+            4
+        );
+        assert_eq!(text_len("a &lt; b &amp;&amp; c"), 10);
+        assert_eq!(text_len("新闻"), 2);
+        // outside of the Basic Multilingual Plane
+        assert_eq!(text_len("😀"), 2);
+    }
+}
@@ -0,0 +1,210 @@
+//! A local HTTP proxy for the Bot API through a SOCKS5 proxy, tbot can't connect
+//! through one itself. It only tunnels `CONNECT` requests carrying its secret.
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+
+use futures::future;
+use percent_encoding::percent_decode_str;
+use ring::rand::{SecureRandom, SystemRandom};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_socks::tcp::Socks5Stream;
+use url::Url;
+
+const MAX_HEAD_LEN: usize = 16 * 1024;
+
+/// Where the bridge listens, and the `Proxy-Authorization` it requires
+pub struct Bridge {
+    pub addr: SocketAddr,
+    pub authorization: String,
+}
+
+/// Start a local HTTP proxy for the SOCKS5 proxy `socks`, on a random port
+pub fn start_bridge(socks: Url) -> io::Result<Bridge> {
+    let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let addr = listener.local_addr()?;
+    let mut listener = TcpListener::from_std(listener)?;
+    let mut secret = [0; 32];
+    SystemRandom::new()
+        .fill(&mut secret)
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "no random source"))?;
+    let secret: String = secret.iter().map(|b| format!("{:02x}", b)).collect();
+    let authorization = format!("Bearer {}", secret);
+
+    let expected = authorization.clone();
+    tokio::spawn(async move {
+        loop {
+            // failures are seen by the client
+            let client = match listener.accept().await {
+                Ok((client, _)) => client,
+                Err(_) => continue,
+            };
+            let (socks, expected) = (socks.clone(), expected.clone());
+            tokio::spawn(async move {
+                let _ignore_result = serve(client, &socks, &expected).await;
+            });
+        }
+    });
+    Ok(Bridge {
+        addr,
+        authorization,
+    })
+}
+
+async fn serve(mut client: TcpStream, socks: &Url, authorization: &str) -> io::Result<()> {
+    let head = read_head(&mut client).await?;
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let target = match (request_line.next(), request_line.next()) {
+        (Some("CONNECT"), Some(target)) => target,
+        _ => return reply(&mut client, "405 Method Not Allowed").await,
+    };
+    let authorized = lines.any(|line| {
+        let mut header = line.splitn(2, ':');
+        let name = header.next().unwrap_or_default().trim();
+        let value = header.next().unwrap_or_default().trim();
+        name.eq_ignore_ascii_case("proxy-authorization")
+            && ring::constant_time::verify_slices_are_equal(
+                value.as_bytes(),
+                authorization.as_bytes(),
+            )
+            .is_ok()
+    });
+    if !authorized {
+        return reply(&mut client, "407 Proxy Authentication Required").await;
+    }
+
+    let mut upstream = match connect(socks, target).await {
+        Ok(upstream) => upstream,
+        Err(_) => return reply(&mut client, "502 Bad Gateway").await,
+    };
+    client
+        .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+        .await?;
+    let (mut client_read, mut client_write) = client.split();
+    let (mut upstream_read, mut upstream_write) = upstream.split();
+    let sending = async {
+        tokio::io::copy(&mut client_read, &mut upstream_write).await?;
+        upstream_write.shutdown().await
+    };
+    let receiving = async {
+        tokio::io::copy(&mut upstream_read, &mut client_write).await?;
+        client_write.shutdown().await
+    };
+    future::try_join(sending, receiving).await?;
+    Ok(())
+}
+
+async fn reply(client: &mut TcpStream, status: &str) -> io::Result<()> {
+    let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+    client.write_all(response.as_bytes()).await
+}
+
+/// Read up to the end of the request head, nothing is sent after it before
+/// the tunnel is established
+async fn read_head(client: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut head = Vec::new();
+    let mut buf = [0; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        if head.len() > MAX_HEAD_LEN {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        let len = client.read(&mut buf).await?;
+        if len == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        head.extend_from_slice(&buf[..len]);
+    }
+    Ok(head)
+}
+
+/// Connect to `target`, a `host:port`, through a SOCKS5 proxy, host names are
+/// resolved locally unless it's a `socks5h` one
+async fn connect(socks: &Url, target: &str) -> io::Result<TcpStream> {
+    let other = |e| io::Error::new(io::ErrorKind::Other, e);
+    let proxy = socks.socket_addrs(|| Some(1080))?[0];
+    let target = if socks.scheme() == "socks5h" {
+        target.to_owned()
+    } else {
+        let addr = tokio::net::lookup_host(target).await?.next();
+        addr.ok_or(io::ErrorKind::NotFound)?.to_string()
+    };
+    let decode = |s| percent_decode_str(s).decode_utf8_lossy().into_owned();
+    let stream = if socks.username().is_empty() {
+        Socks5Stream::connect(proxy, target).await
+    } else {
+        let username = decode(socks.username());
+        let password = decode(socks.password().unwrap_or_default());
+        Socks5Stream::connect_with_password(proxy, target, &username, &password).await
+    };
+    Ok(stream.map_err(other)?.into_inner())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A SOCKS5 proxy which is also the server, for `example.com:443`
+    async fn fake_proxy(mut listener: TcpListener) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut greeting = [0; 2];
+        stream.read_exact(&mut greeting).await.unwrap();
+        let mut methods = vec![0; greeting[1] as usize];
+        stream.read_exact(&mut methods).await.unwrap();
+        assert!(methods.contains(&2));
+        stream.write_all(&[5, 2]).await.unwrap();
+        let mut auth = [0; 11];
+        stream.read_exact(&mut auth).await.unwrap();
+        assert_eq!(&auth, b"\x01\x04user\x04p@ss");
+        stream.write_all(&[1, 0]).await.unwrap();
+        let mut request = [0; 5 + 11 + 2];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request, b"\x05\x01\x00\x03\x0bexample.com\x01\xbb");
+        stream
+            .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0])
+            .await
+            .unwrap();
+
+        let mut ping = [0; 4];
+        stream.read_exact(&mut ping).await.unwrap();
+        assert_eq!(&ping, b"ping");
+        stream.write_all(b"pong").await.unwrap();
+    }
+
+    async fn connect_request(bridge: SocketAddr, authorization: &str) -> TcpStream {
+        let mut client = TcpStream::connect(bridge).await.unwrap();
+        let request = format!(
+            "CONNECT example.com:443 HTTP/1.1\r\nProxy-Authorization: {}\r\n\r\n",
+            authorization
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+        client
+    }
+
+    #[tokio::test]
+    async fn bridge() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let socks = format!("socks5h://user:p%40ss@{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(fake_proxy(listener));
+        let bridge = start_bridge(Url::parse(&socks).unwrap()).unwrap();
+
+        // only for those who know the secret
+        let mut client = connect_request(bridge.addr, "Bearer guess").await;
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 407 "), "{}", response);
+
+        let mut client = connect_request(bridge.addr, &bridge.authorization).await;
+        let established = b"HTTP/1.1 200 Connection Established\r\n\r\n";
+        let mut response = vec![0; established.len()];
+        client.read_exact(&mut response).await.unwrap();
+        assert_eq!(&response[..], &established[..]);
+        client.write_all(b"ping").await.unwrap();
+        let mut pong = [0; 4];
+        client.read_exact(&mut pong).await.unwrap();
+        assert_eq!(&pong, b"pong");
+        server.await.unwrap();
+    }
+}
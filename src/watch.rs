@@ -0,0 +1,326 @@
+//! Web pages without feeds are watched through a CSS selector,
+//! the matched elements, or the changes of the selection, become items
+
+mod html;
+
+use std::collections::HashSet;
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use thiserror::Error;
+use url::Url;
+
+use crate::feed::{Item, Rss};
+use crate::messages::Escape;
+use html::{Document, NodeId, Selector};
+
+pub use html::SelectorError;
+
+/// The selector is kept in the fragment of the feed link, after one of them
+const ELEMENTS_FRAGMENT: &str = "rssbot-watch=";
+const DIFF_FRAGMENT: &str = "rssbot-diff=";
+const TITLE_MAX_CHARS: usize = 100;
+
+#[derive(Error, Debug)]
+pub enum WatchError {
+    #[error("invalid CSS selector: {0}")]
+    Selector(#[from] SelectorError),
+    #[error("nothing on the page matches the selector")]
+    NoMatch,
+}
+
+/// A watched page, stored in the database as a feed link
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watch {
+    pub url: Url,
+    pub selector: String,
+    /// Push the changes of the whole selection instead of new elements
+    pub diff: bool,
+}
+
+impl Watch {
+    /// `args` are the words after the URL in `/sub`, `[diff] <CSS selector>`
+    pub fn from_args(url: &str, args: &[&str]) -> Option<Self> {
+        let (diff, selector) = match args {
+            ["diff", selector @ ..] if !selector.is_empty() => (true, selector),
+            _ => (false, args),
+        };
+        let mut url = Url::parse(url).ok()?;
+        url.set_fragment(None);
+        Some(Watch {
+            url,
+            selector: selector.join(" "),
+            diff,
+        })
+    }
+
+    /// `None` if the link is not a watched page
+    pub fn from_link(link: &str) -> Option<Self> {
+        let mut url = Url::parse(link).ok()?;
+        let (diff, selector) = {
+            let fragment = url.fragment()?;
+            let (diff, selector) = if fragment.starts_with(ELEMENTS_FRAGMENT) {
+                (false, &fragment[ELEMENTS_FRAGMENT.len()..])
+            } else if fragment.starts_with(DIFF_FRAGMENT) {
+                (true, &fragment[DIFF_FRAGMENT.len()..])
+            } else {
+                return None;
+            };
+            let selector = percent_decode_str(selector).decode_utf8().ok()?;
+            (diff, selector.into_owned())
+        };
+        url.set_fragment(None);
+        Some(Watch {
+            url,
+            selector,
+            diff,
+        })
+    }
+
+    pub fn link(&self) -> String {
+        let prefix = if self.diff {
+            DIFF_FRAGMENT
+        } else {
+            ELEMENTS_FRAGMENT
+        };
+        let selector = utf8_percent_encode(&self.selector, NON_ALPHANUMERIC);
+        let mut url = self.url.clone();
+        url.set_fragment(Some(&format!("{}{}", prefix, selector)));
+        url.into_string()
+    }
+}
+
+/// Turn a page fetched from `url` into a feed
+///
+/// A diff watch has a single item with the text of the selection,
+/// it's turned into the changes by `diff_items`.
+pub fn page_to_feed(html: &str, url: &Url, watch: &Watch) -> Result<Rss, WatchError> {
+    let selector = Selector::parse(&watch.selector)?;
+    let doc = Document::parse(html);
+    let selected = selector.select(&doc);
+    if selected.is_empty() {
+        return Err(WatchError::NoMatch);
+    }
+    let title = Selector::parse("title")
+        .unwrap()
+        .select(&doc)
+        .first()
+        .map(|&id| doc.text(id))
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| url.to_string());
+
+    let items = if watch.diff {
+        // the text of nested matches is already in their ancestors
+        let lines = selected
+            .iter()
+            .filter(|&&id| !selected.iter().any(|&other| doc.is_ancestor(other, id)))
+            .flat_map(|&id| doc.text_lines(id))
+            .collect::<Vec<_>>()
+            .join("\n");
+        vec![Item {
+            title: Some(title.clone()),
+            link: Some(url.to_string()),
+            id: Some(lines.clone()),
+            content: Some(lines),
+            ..Default::default()
+        }]
+    } else {
+        selected
+            .iter()
+            .filter_map(|&id| element_to_item(&doc, id, url))
+            .collect()
+    };
+    Ok(Rss {
+        title,
+        link: url.to_string(),
+        items,
+        ..Default::default()
+    })
+}
+
+/// The title is the first heading in the element or its text, the link is
+/// the first link in it, or the element itself if it has an id
+fn element_to_item(doc: &Document, id: NodeId, url: &Url) -> Option<Item> {
+    let text = doc.text(id);
+    let html = doc.inner_html(id);
+    if text.is_empty() && html.trim().is_empty() {
+        return None;
+    }
+    let elements = || std::iter::once(id).chain(doc.descendants(id));
+    let title = elements()
+        .find(|&e| matches!(doc.name(e), "h1" | "h2" | "h3" | "h4" | "h5" | "h6"))
+        .map(|e| doc.text(e))
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| text.clone());
+    let link = elements()
+        .filter(|&e| doc.name(e) == "a")
+        .filter_map(|e| url.join(doc.attr(e, "href")?).ok())
+        .find(|link| link.scheme() == "http" || link.scheme() == "https")
+        .or_else(|| {
+            let mut link = url.clone();
+            link.set_fragment(Some(doc.attr(id, "id")?));
+            Some(link)
+        })
+        .unwrap_or_else(|| url.clone());
+    Some(Item {
+        title: Some(truncate(&title)),
+        link: Some(link.into_string()),
+        // elements with the same text are the same item
+        id: Some(if text.is_empty() { html.clone() } else { text }),
+        summary: Some(html),
+        ..Default::default()
+    })
+}
+
+/// Turn the item of a diff watch into the changes since the `previous` text,
+/// no item is left if nothing changed
+pub fn diff_items(rss: &mut Rss, previous: Option<&str>) {
+    let (previous, item) = match (previous, rss.items.first_mut()) {
+        (Some(previous), Some(item)) => (previous, item),
+        // the first fetch is the same as the one at subscription
+        _ => return,
+    };
+    let current = item.content.clone().unwrap_or_default();
+    if current == previous {
+        rss.items.clear();
+        return;
+    }
+    let old_lines: HashSet<&str> = previous.lines().collect();
+    let new_lines: HashSet<&str> = current.lines().collect();
+    let changes: Vec<String> = current
+        .lines()
+        .filter(|line| !old_lines.contains(line))
+        .map(|line| format!("+ {}", line))
+        .chain(
+            previous
+                .lines()
+                .filter(|line| !new_lines.contains(line))
+                .map(|line| format!("- {}", line)),
+        )
+        .collect();
+    // a change back to an earlier text is a new item too
+    item.id = Some(format!("{}\n\n{}", previous, current));
+    if let Some(change) = changes.first() {
+        item.title = Some(truncate(change));
+    }
+    item.summary = Some(
+        changes
+            .iter()
+            .map(|change| Escape(change).to_string())
+            .collect::<Vec<_>>()
+            .join("<br>"),
+    );
+}
+
+fn truncate(s: &str) -> String {
+    if s.chars().count() <= TITLE_MAX_CHARS {
+        return s.to_owned();
+    }
+    let mut truncated: String = s.chars().take(TITLE_MAX_CHARS - 1).collect();
+    truncated.truncate(truncated.trim_end().len());
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PAGE: &str = r#"<html><head><title>Status</title></head><body>
+        <ul id="news">
+            <li id="n1"><h3>Release 1.0</h3><p>Out now</p></li>
+            <li><a href="javascript:void(0)">x</a> <a href="/2">Release 2.0</a></li>
+        </ul>
+        <div class="status"><p>API: up</p><p>Web: up</p></div>
+        </body></html>"#;
+
+    #[test]
+    fn links() {
+        let watch = Watch::from_args(
+            "https://example.com/news#top",
+            &["diff", "ul#news", ">", "li"],
+        )
+        .unwrap();
+        assert_eq!(watch.selector, "ul#news > li");
+        assert!(watch.diff);
+        let link = watch.link();
+        assert_eq!(
+            link,
+            "https://example.com/news#rssbot-diff=ul%23news%20%3E%20li"
+        );
+        assert_eq!(Watch::from_link(&link), Some(watch));
+
+        let watch = Watch::from_args("https://example.com/", &["diff"]).unwrap();
+        assert_eq!(watch.selector, "diff");
+        assert!(!watch.diff);
+        assert_eq!(Watch::from_link("https://example.com/#top"), None);
+        assert_eq!(Watch::from_link("https://example.com/feed.xml"), None);
+    }
+
+    #[test]
+    fn elements() {
+        let url = Url::parse("https://example.com/news").unwrap();
+        let watch = Watch::from_args(url.as_str(), &["#news", "li"]).unwrap();
+        let rss = page_to_feed(PAGE, &url, &watch).unwrap();
+        assert_eq!(rss.title, "Status");
+        assert_eq!(rss.link, "https://example.com/news");
+        let items: Vec<_> = rss
+            .items
+            .iter()
+            .map(|item| {
+                (
+                    item.title.as_deref().unwrap(),
+                    item.link.as_deref().unwrap(),
+                    item.id.as_deref().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            items,
+            vec![
+                (
+                    "Release 1.0",
+                    "https://example.com/news#n1",
+                    "Release 1.0 Out now"
+                ),
+                ("x Release 2.0", "https://example.com/2", "x Release 2.0"),
+            ]
+        );
+        assert_eq!(
+            rss.items[0].summary.as_deref(),
+            Some("<h3>Release 1.0</h3><p>Out now</p>")
+        );
+
+        let watch = Watch::from_args(url.as_str(), &[".missing"]).unwrap();
+        assert!(matches!(
+            page_to_feed(PAGE, &url, &watch),
+            Err(WatchError::NoMatch)
+        ));
+        let watch = Watch::from_args(url.as_str(), &["li:first-child"]).unwrap();
+        assert!(matches!(
+            page_to_feed(PAGE, &url, &watch),
+            Err(WatchError::Selector(SelectorError::Unexpected(':')))
+        ));
+    }
+
+    #[test]
+    fn diff() {
+        let url = Url::parse("https://example.com/").unwrap();
+        let watch = Watch::from_args(url.as_str(), &["diff", ".status"]).unwrap();
+        let before = page_to_feed(PAGE, &url, &watch).unwrap();
+        let text = before.items[0].content.clone().unwrap();
+        assert_eq!(text, "API: up\nWeb: up");
+
+        let mut unchanged = before.clone();
+        diff_items(&mut unchanged, Some(&text));
+        assert!(unchanged.items.is_empty());
+
+        let page = PAGE.replace("API: up", "API: down");
+        let mut after = page_to_feed(&page, &url, &watch).unwrap();
+        diff_items(&mut after, Some(&text));
+        let item = &after.items[0];
+        assert_eq!(item.title.as_deref(), Some("+ API: down"));
+        assert_eq!(item.summary.as_deref(), Some("+ API: down<br>- API: up"));
+        assert_ne!(item.id, before.items[0].id);
+        assert_eq!(item.content.as_deref(), Some("API: down\nWeb: up"));
+    }
+}
@@ -0,0 +1,727 @@
+//! A lenient HTML tree and the CSS selectors to query it,
+//! enough to pick the interesting parts of a page
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use thiserror::Error;
+
+use crate::feed::decode_html_entities;
+use crate::messages::Escape;
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+/// Their content is text, not markup
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style", "textarea", "title"];
+/// Their text is on its own lines
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+pub type NodeId = usize;
+
+/// A step of a walk through the tree, without recursing so deep documents
+/// can't overflow the stack
+enum Visit {
+    Enter(NodeId),
+    Leave(NodeId),
+}
+
+#[derive(Debug)]
+enum NodeData {
+    Element {
+        name: String,
+        attrs: Vec<(String, String)>,
+    },
+    Text(String),
+}
+
+#[derive(Debug)]
+struct Node {
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    data: NodeData,
+}
+
+/// Nodes in document order, the first one is the root
+#[derive(Debug)]
+pub struct Document {
+    nodes: Vec<Node>,
+}
+
+impl Document {
+    /// Never fails, unclosed and stray tags are handled like browsers do in most cases
+    pub fn parse(html: &str) -> Self {
+        lazy_static! {
+            static ref TAG: Regex = Regex::new(
+                r#"(?s)<!--.*?-->|<![^>]*>|<\?[^>]*>|<(/?)([a-zA-Z][a-zA-Z0-9:-]*)((?:[^>"']|"[^"]*"|'[^']*')*)>"#
+            )
+            .unwrap();
+            static ref ATTR: Regex =
+                Regex::new(r#"([^\s/>"'=]+)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+)))?"#)
+                    .unwrap();
+        }
+        let mut doc = Document {
+            nodes: vec![Node {
+                parent: None,
+                children: Vec::new(),
+                data: NodeData::Element {
+                    name: String::new(),
+                    attrs: Vec::new(),
+                },
+            }],
+        };
+        let mut open = vec![0];
+        let mut pos = 0;
+        while let Some(caps) = TAG.captures(&html[pos..]) {
+            let whole = caps.get(0).unwrap();
+            doc.push_text(&open, &html[pos..pos + whole.start()]);
+            pos += whole.end();
+            let name = match caps.get(2) {
+                Some(name) => name.as_str().to_ascii_lowercase(),
+                // comments, doctypes and processing instructions
+                None => continue,
+            };
+            if &caps[1] == "/" {
+                if let Some(i) = open.iter().rposition(|&id| doc.name(id) == name) {
+                    open.truncate(i);
+                }
+                continue;
+            }
+
+            while open.len() > 1 && closes(&name, doc.name(*open.last().unwrap())) {
+                open.pop();
+            }
+            let attrs = ATTR
+                .captures_iter(&caps[3])
+                .map(|attr| {
+                    let value = attr
+                        .get(2)
+                        .or_else(|| attr.get(3))
+                        .or_else(|| attr.get(4))
+                        .map_or("", |m| m.as_str());
+                    (
+                        attr[1].to_ascii_lowercase(),
+                        decode_html_entities(value).into_owned(),
+                    )
+                })
+                .collect();
+            let is_void = VOID_ELEMENTS.contains(&&*name) || caps[3].trim_end().ends_with('/');
+            let is_raw_text = RAW_TEXT_ELEMENTS.contains(&&*name);
+            let id = doc.push(*open.last().unwrap(), NodeData::Element { name, attrs });
+            if is_void {
+                continue;
+            }
+            open.push(id);
+            if is_raw_text {
+                let end_tag = format!("</{}", doc.name(id));
+                let len = find_ignore_case(&html[pos..], &end_tag).unwrap_or(html.len() - pos);
+                doc.push_text(&open, &html[pos..pos + len]);
+                pos += len;
+            }
+        }
+        doc.push_text(&open, &html[pos..]);
+        doc
+    }
+
+    fn push(&mut self, parent: NodeId, data: NodeData) -> NodeId {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            parent: Some(parent),
+            children: Vec::new(),
+            data,
+        });
+        self.nodes[parent].children.push(id);
+        id
+    }
+
+    fn push_text(&mut self, open: &[NodeId], text: &str) {
+        if !text.is_empty() {
+            let text = decode_html_entities(text).into_owned();
+            self.push(*open.last().unwrap(), NodeData::Text(text));
+        }
+    }
+
+    /// Tag name of an element, empty for the root and texts
+    pub fn name(&self, id: NodeId) -> &str {
+        match &self.nodes[id].data {
+            NodeData::Element { name, .. } => name,
+            NodeData::Text(_) => "",
+        }
+    }
+
+    pub fn attr(&self, id: NodeId, name: &str) -> Option<&str> {
+        match &self.nodes[id].data {
+            NodeData::Element { attrs, .. } => attrs
+                .iter()
+                .find(|(attr, _)| attr == name)
+                .map(|(_, value)| value.as_str()),
+            NodeData::Text(_) => None,
+        }
+    }
+
+    fn is_element(&self, id: NodeId) -> bool {
+        id != 0 && matches!(self.nodes[id].data, NodeData::Element { .. })
+    }
+
+    fn parent_element(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id].parent.filter(|&parent| parent != 0)
+    }
+
+    pub fn is_ancestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        let mut parent = self.nodes[id].parent;
+        while let Some(id) = parent {
+            if id == ancestor {
+                return true;
+            }
+            parent = self.nodes[id].parent;
+        }
+        false
+    }
+
+    /// Descendant elements in document order
+    pub fn descendants(&self, id: NodeId) -> Vec<NodeId> {
+        let mut descendants = Vec::new();
+        let mut stack: Vec<NodeId> = self.nodes[id].children.iter().rev().copied().collect();
+        while let Some(id) = stack.pop() {
+            if self.is_element(id) {
+                descendants.push(id);
+                stack.extend(self.nodes[id].children.iter().rev());
+            }
+        }
+        descendants
+    }
+
+    /// Text of an element, blocks are on their own lines
+    pub fn text_lines(&self, id: NodeId) -> Vec<String> {
+        let mut lines = vec![String::new()];
+        self.collect_lines(id, &mut lines);
+        lines
+            .iter()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|line| !line.is_empty())
+            .collect()
+    }
+
+    fn collect_lines(&self, id: NodeId, lines: &mut Vec<String>) {
+        let mut stack = vec![Visit::Enter(id)];
+        while let Some(visit) = stack.pop() {
+            let id = match visit {
+                Visit::Enter(id) => id,
+                // only blocks are left
+                Visit::Leave(_) => {
+                    lines.push(String::new());
+                    continue;
+                }
+            };
+            match &self.nodes[id].data {
+                NodeData::Text(text) => lines.last_mut().unwrap().push_str(text),
+                NodeData::Element { name, .. } if name == "script" || name == "style" => (),
+                NodeData::Element { name, .. } => {
+                    if BLOCK_ELEMENTS.contains(&name.as_str()) {
+                        lines.push(String::new());
+                        stack.push(Visit::Leave(id));
+                    }
+                    let children = self.nodes[id].children.iter().rev();
+                    stack.extend(children.map(|&child| Visit::Enter(child)));
+                }
+            }
+        }
+    }
+
+    /// Text of an element on a single line
+    pub fn text(&self, id: NodeId) -> String {
+        self.text_lines(id).join(" ")
+    }
+
+    pub fn inner_html(&self, id: NodeId) -> String {
+        let mut html = String::new();
+        for &child in &self.nodes[id].children {
+            self.write_html(child, &mut html);
+        }
+        html
+    }
+
+    fn write_html(&self, id: NodeId, html: &mut String) {
+        let mut stack = vec![Visit::Enter(id)];
+        while let Some(visit) = stack.pop() {
+            let id = match visit {
+                Visit::Enter(id) => id,
+                Visit::Leave(id) => {
+                    html.push_str(&format!("</{}>", self.name(id)));
+                    continue;
+                }
+            };
+            match &self.nodes[id].data {
+                NodeData::Text(text) => html.push_str(&Escape(text).to_string()),
+                NodeData::Element { name, attrs } => {
+                    html.push('<');
+                    html.push_str(name);
+                    for (attr, value) in attrs {
+                        html.push_str(&format!(" {}=\"{}\"", attr, Escape(value)));
+                    }
+                    html.push('>');
+                    if VOID_ELEMENTS.contains(&name.as_str()) {
+                        continue;
+                    }
+                    stack.push(Visit::Leave(id));
+                    let children = self.nodes[id].children.iter().rev();
+                    stack.extend(children.map(|&child| Visit::Enter(child)));
+                }
+            }
+        }
+    }
+}
+
+/// Byte offset of an ASCII `needle`, in any case
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Whether the start tag `new` implies the end of the open element
+fn closes(new: &str, open: &str) -> bool {
+    match open {
+        "p" => matches!(
+            new,
+            "address"
+                | "article"
+                | "aside"
+                | "blockquote"
+                | "div"
+                | "dl"
+                | "fieldset"
+                | "figure"
+                | "footer"
+                | "form"
+                | "h1"
+                | "h2"
+                | "h3"
+                | "h4"
+                | "h5"
+                | "h6"
+                | "header"
+                | "hr"
+                | "main"
+                | "nav"
+                | "ol"
+                | "p"
+                | "pre"
+                | "section"
+                | "table"
+                | "ul"
+        ),
+        "li" => new == "li",
+        "dt" | "dd" => new == "dt" || new == "dd",
+        "td" | "th" => matches!(new, "td" | "th" | "tr"),
+        "tr" => new == "tr",
+        "option" => new == "option",
+        _ => false,
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SelectorError {
+    #[error("unexpected `{0}`")]
+    Unexpected(char),
+    #[error("unexpected end")]
+    UnexpectedEnd,
+}
+
+/// A group of selectors like `h2 > a, .title`, with type, `#id`, `.class`
+/// and attribute selectors, and descendant and child combinators
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector(Vec<Complex>);
+
+#[derive(Debug, Clone, PartialEq)]
+struct Complex {
+    compounds: Vec<Compound>,
+    /// `combinators[i]` is between `compounds[i]` and `compounds[i + 1]`
+    combinators: Vec<Combinator>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Compound {
+    /// `None` is any element
+    name: Option<String>,
+    conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Id(String),
+    Class(String),
+    Attr {
+        name: String,
+        value: Option<(AttrOp, String)>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AttrOp {
+    /// `=`
+    Equals,
+    /// `~=`
+    Includes,
+    /// `^=`
+    Prefix,
+    /// `$=`
+    Suffix,
+    /// `*=`
+    Substring,
+}
+
+impl Selector {
+    pub fn parse(s: &str) -> Result<Self, SelectorError> {
+        let mut chars = s.chars().peekable();
+        let mut group = Vec::new();
+        loop {
+            group.push(parse_complex(&mut chars)?);
+            match chars.next() {
+                None => break,
+                Some(',') => (),
+                Some(c) => return Err(SelectorError::Unexpected(c)),
+            }
+        }
+        Ok(Selector(group))
+    }
+
+    /// Matched elements in document order
+    pub fn select(&self, doc: &Document) -> Vec<NodeId> {
+        (0..doc.nodes.len())
+            .filter(|&id| doc.is_element(id))
+            .filter(|&id| {
+                self.0
+                    .iter()
+                    .any(|complex| complex.matches(doc, id, complex.compounds.len() - 1))
+            })
+            .collect()
+    }
+}
+
+impl Complex {
+    /// Whether `compounds[..=i]` matches, ending at the element
+    fn matches(&self, doc: &Document, id: NodeId, i: usize) -> bool {
+        if !self.compounds[i].matches(doc, id) {
+            return false;
+        }
+        if i == 0 {
+            return true;
+        }
+        match self.combinators[i - 1] {
+            Combinator::Child => doc
+                .parent_element(id)
+                .map_or(false, |parent| self.matches(doc, parent, i - 1)),
+            Combinator::Descendant => {
+                let mut ancestor = doc.parent_element(id);
+                while let Some(id) = ancestor {
+                    if self.matches(doc, id, i - 1) {
+                        return true;
+                    }
+                    ancestor = doc.parent_element(id);
+                }
+                false
+            }
+        }
+    }
+}
+
+impl Compound {
+    fn matches(&self, doc: &Document, id: NodeId) -> bool {
+        if matches!(&self.name, Some(name) if name != doc.name(id)) {
+            return false;
+        }
+        self.conditions.iter().all(|condition| match condition {
+            Condition::Id(expected) => doc.attr(id, "id") == Some(expected),
+            Condition::Class(class) => doc.attr(id, "class").map_or(false, |classes| {
+                classes.split_whitespace().any(|c| c == class)
+            }),
+            Condition::Attr { name, value } => match (doc.attr(id, name), value) {
+                (None, _) => false,
+                (Some(_), None) => true,
+                (Some(actual), Some((op, expected))) => match op {
+                    AttrOp::Equals => actual == expected,
+                    AttrOp::Includes => actual.split_whitespace().any(|word| word == expected),
+                    AttrOp::Prefix => !expected.is_empty() && actual.starts_with(&**expected),
+                    AttrOp::Suffix => !expected.is_empty() && actual.ends_with(&**expected),
+                    AttrOp::Substring => !expected.is_empty() && actual.contains(&**expected),
+                },
+            },
+        })
+    }
+}
+
+fn parse_complex(chars: &mut Peekable<Chars>) -> Result<Complex, SelectorError> {
+    skip_whitespace(chars);
+    let mut compounds = vec![parse_compound(chars)?];
+    let mut combinators = Vec::new();
+    loop {
+        let spaced = skip_whitespace(chars);
+        let combinator = match chars.peek() {
+            None | Some(',') => break,
+            Some('>') => {
+                chars.next();
+                skip_whitespace(chars);
+                Combinator::Child
+            }
+            Some(_) if spaced => Combinator::Descendant,
+            Some(&c) => return Err(SelectorError::Unexpected(c)),
+        };
+        combinators.push(combinator);
+        compounds.push(parse_compound(chars)?);
+    }
+    Ok(Complex {
+        compounds,
+        combinators,
+    })
+}
+
+fn parse_compound(chars: &mut Peekable<Chars>) -> Result<Compound, SelectorError> {
+    let mut compound = Compound::default();
+    let mut is_empty = true;
+    match chars.peek() {
+        Some('*') => {
+            chars.next();
+            is_empty = false;
+        }
+        Some(&c) if is_ident_char(c) => {
+            compound.name = Some(parse_ident(chars)?.to_ascii_lowercase());
+            is_empty = false;
+        }
+        _ => (),
+    }
+    loop {
+        let condition = match chars.peek() {
+            Some('#') => {
+                chars.next();
+                Condition::Id(parse_ident(chars)?)
+            }
+            Some('.') => {
+                chars.next();
+                Condition::Class(parse_ident(chars)?)
+            }
+            Some('[') => {
+                chars.next();
+                parse_attr(chars)?
+            }
+            _ => break,
+        };
+        compound.conditions.push(condition);
+        is_empty = false;
+    }
+    if is_empty {
+        return Err(unexpected(chars.next()));
+    }
+    Ok(compound)
+}
+
+/// The part after `[`
+fn parse_attr(chars: &mut Peekable<Chars>) -> Result<Condition, SelectorError> {
+    skip_whitespace(chars);
+    let name = parse_ident(chars)?.to_ascii_lowercase();
+    skip_whitespace(chars);
+    let op = match chars.next() {
+        Some(']') => return Ok(Condition::Attr { name, value: None }),
+        Some('=') => AttrOp::Equals,
+        Some(c) => {
+            let op = match c {
+                '~' => AttrOp::Includes,
+                '^' => AttrOp::Prefix,
+                '$' => AttrOp::Suffix,
+                '*' => AttrOp::Substring,
+                _ => return Err(SelectorError::Unexpected(c)),
+            };
+            match chars.next() {
+                Some('=') => op,
+                c => return Err(unexpected(c)),
+            }
+        }
+        None => return Err(SelectorError::UnexpectedEnd),
+    };
+    skip_whitespace(chars);
+    let value = match chars.peek() {
+        Some(&quote) if quote == '"' || quote == '\'' => {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some(c) if c == quote => break,
+                    Some(c) => value.push(c),
+                    None => return Err(SelectorError::UnexpectedEnd),
+                }
+            }
+            value
+        }
+        _ => parse_ident(chars)?,
+    };
+    skip_whitespace(chars);
+    match chars.next() {
+        Some(']') => Ok(Condition::Attr {
+            name,
+            value: Some((op, value)),
+        }),
+        c => Err(unexpected(c)),
+    }
+}
+
+fn parse_ident(chars: &mut Peekable<Chars>) -> Result<String, SelectorError> {
+    let mut ident = String::new();
+    while let Some(&c) = chars.peek() {
+        if !is_ident_char(c) {
+            break;
+        }
+        ident.push(c);
+        chars.next();
+    }
+    if ident.is_empty() {
+        return Err(unexpected(chars.next()));
+    }
+    Ok(ident)
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_' || !c.is_ascii()
+}
+
+/// Whether any whitespace is skipped
+fn skip_whitespace(chars: &mut Peekable<Chars>) -> bool {
+    let mut skipped = false;
+    while chars.peek().map_or(false, |c| c.is_whitespace()) {
+        chars.next();
+        skipped = true;
+    }
+    skipped
+}
+
+fn unexpected(c: Option<char>) -> SelectorError {
+    c.map_or(SelectorError::UnexpectedEnd, SelectorError::Unexpected)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn select(doc: &Document, selector: &str) -> Vec<String> {
+        Selector::parse(selector)
+            .unwrap()
+            .select(doc)
+            .into_iter()
+            .map(|id| doc.text(id))
+            .collect()
+    }
+
+    #[test]
+    fn lenient_parsing() {
+        let doc = Document::parse(
+            r#"<!DOCTYPE html><html><head><title>a &amp; b</title>
+            <script>if (a <b) { document.write("</div>") }</script></head>
+            <body><p>one<p>two<ul><li>three<li>four <br> five</ul>
+            <img src=x.png alt='<img>'><div class="x y">six</span></div>"#,
+        );
+        assert_eq!(select(&doc, "title"), vec!["a & b"]);
+        assert_eq!(select(&doc, "p"), vec!["one", "two"]);
+        assert_eq!(select(&doc, "ul > li"), vec!["three", "four five"]);
+        assert_eq!(select(&doc, "div"), vec!["six"]);
+        let img = Selector::parse("img").unwrap().select(&doc)[0];
+        assert_eq!(doc.attr(img, "alt"), Some("<img>"));
+        let li = Selector::parse("li").unwrap().select(&doc)[1];
+        assert_eq!(doc.text_lines(li), vec!["four", "five"]);
+        assert_eq!(doc.inner_html(li), "four <br> five");
+
+        let doc = Document::parse("<TITLE>a</Title><title>b</TITLE>");
+        assert_eq!(select(&doc, "title"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let depth = 100_000;
+        let html = format!("{}text{}", "<div>".repeat(depth), "</div>".repeat(depth));
+        let doc = Document::parse(&html);
+        assert_eq!(doc.descendants(0).len(), depth);
+        assert_eq!(doc.text(0), "text");
+        assert_eq!(doc.inner_html(0), html);
+    }
+
+    #[test]
+    fn selectors() {
+        let doc = Document::parse(
+            r#"<div id="main">
+            <article class="post new"><h2><a href="/1">first</a></h2></article>
+            <article class="post"><div><h2><a href="/2" data-x="a b">second</a></h2></div></article>
+            </div><h2>other</h2>"#,
+        );
+        assert_eq!(select(&doc, "h2"), vec!["first", "second", "other"]);
+        assert_eq!(select(&doc, "#main h2"), vec!["first", "second"]);
+        assert_eq!(select(&doc, ".post > h2"), vec!["first"]);
+        assert_eq!(select(&doc, "article.post.new a"), vec!["first"]);
+        assert_eq!(select(&doc, "DIV > *"), vec!["first", "second", "second"]);
+        assert_eq!(select(&doc, "[data-x]"), vec!["second"]);
+        assert_eq!(select(&doc, "a[href='/2']"), vec!["second"]);
+        assert_eq!(select(&doc, "a[href^=\"/\"]"), vec!["first", "second"]);
+        assert_eq!(
+            select(&doc, "[data-x~=b], [href$=\"1\"]"),
+            vec!["first", "second"]
+        );
+        assert_eq!(select(&doc, "main"), Vec::<String>::new());
+
+        assert_eq!(Selector::parse("a >"), Err(SelectorError::UnexpectedEnd));
+        assert_eq!(
+            Selector::parse("a:hover"),
+            Err(SelectorError::Unexpected(':'))
+        );
+        assert_eq!(
+            Selector::parse("a + b"),
+            Err(SelectorError::Unexpected('+'))
+        );
+        assert_eq!(
+            Selector::parse("[x|=y]"),
+            Err(SelectorError::Unexpected('|'))
+        );
+        assert_eq!(Selector::parse(""), Err(SelectorError::UnexpectedEnd));
+    }
+}
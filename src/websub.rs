@@ -0,0 +1,523 @@
+//! WebSub subscriber
+//!
+//! https://www.w3.org/TR/websub/
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use hyper::{
+    body::HttpBody,
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use once_cell::sync::OnceCell;
+use ring::{
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+use tbot::Bot;
+use url::{form_urlencoded, Url};
+
+use crate::client;
+use crate::data::{Database, Hub};
+use crate::feed::Rss;
+
+/// Public URL of the callback server, WebSub is disabled without it
+static BASE_URL: OnceCell<Url> = OnceCell::new();
+
+const CALLBACK_PATH: &str = "websub";
+/// Leases are renewed this long before they expire
+const RENEW_BEFORE: Duration = Duration::from_secs(24 * 60 * 60);
+/// For hubs which don't tell how long the lease is
+const DEFAULT_LEASE: Duration = Duration::from_secs(10 * 24 * 60 * 60);
+/// Subscriptions not verified in time are requested again
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+pub fn init(base_url: Url) {
+    BASE_URL
+        .set(base_url)
+        .expect("BASE_URL already initialized");
+}
+
+/// Start the callback server, return the address it's listening on
+pub fn start(bot: Bot, db: Arc<Mutex<Database>>, addr: SocketAddr) -> hyper::Result<SocketAddr> {
+    let make_service = make_service_fn(move |_| {
+        let bot = bot.clone();
+        let db = db.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(bot.clone(), db.clone(), req))) }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    let addr = server.local_addr();
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            crate::print_error(e);
+        }
+    });
+    Ok(addr)
+}
+
+/// Subscribe to the hub advertised by the feed, or renew the lease
+pub async fn subscribe_if_needed(db: &Arc<Mutex<Database>>, rss_link: &str, rss: &Rss) {
+    let base_url = match BASE_URL.get() {
+        Some(url) => url,
+        None => return,
+    };
+    let hub_link = match &rss.hub {
+        Some(link) => link,
+        None => return,
+    };
+    let topic = rss.source.as_deref().unwrap_or(rss_link);
+    let current = match db.lock().unwrap().get_feed(rss_link) {
+        Some(feed) => feed.hub,
+        // user unsubscribed while fetching the feed
+        None => return,
+    };
+
+    let now = SystemTime::now();
+    let hub = match current {
+        Some(hub) if hub.link == *hub_link && hub.topic == topic => {
+            let due = hub.expires_at.map_or(true, |t| t < now + RENEW_BEFORE);
+            let waiting = hub.requested_at.map_or(false, |t| now < t + VERIFY_TIMEOUT);
+            if !due || waiting {
+                return;
+            }
+            hub
+        }
+        // the feed has a new hub, the old one will get 410 Gone on its next push
+        _ => Hub {
+            link: hub_link.clone(),
+            topic: topic.to_owned(),
+            callback: callback_url(base_url, &random_hex(16)),
+            secret: random_hex(32),
+            requested_at: None,
+            expires_at: None,
+        },
+    };
+    let hub = Hub {
+        requested_at: Some(now),
+        ..hub
+    };
+    // hubs may verify before responding, the callback must know the subscription
+    db.lock().unwrap().set_hub(rss_link, Some(hub.clone()));
+
+    let form = [
+        ("hub.mode", "subscribe"),
+        ("hub.topic", &hub.topic),
+        ("hub.callback", &hub.callback),
+        ("hub.secret", &hub.secret),
+    ];
+    if let Err(e) = client::websub_request(&hub.link, &form).await {
+        crate::print_error(e);
+    }
+}
+
+pub async fn unsubscribe(hub: &Hub) {
+    let form = [
+        ("hub.mode", "unsubscribe"),
+        ("hub.topic", &hub.topic),
+        ("hub.callback", &hub.callback),
+    ];
+    if let Err(e) = client::websub_request(&hub.link, &form).await {
+        crate::print_error(e);
+    }
+}
+
+async fn handle(
+    bot: Bot,
+    db: Arc<Mutex<Database>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let callback = match callback_of_path(req.uri().path()) {
+        Some(callback) => callback,
+        None => return Ok(status(StatusCode::NOT_FOUND)),
+    };
+    let resp = match *req.method() {
+        Method::GET => verify_intent(&db, &callback, req.uri().query().unwrap_or_default()),
+        Method::POST => receive_content(bot, db, &callback, req).await,
+        _ => status(StatusCode::METHOD_NOT_ALLOWED),
+    };
+    Ok(resp)
+}
+
+fn verify_intent(db: &Arc<Mutex<Database>>, callback: &str, query: &str) -> Response<Body> {
+    let (mut mode, mut topic, mut challenge, mut lease_seconds) = (None, None, None, None);
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        match &*key {
+            "hub.mode" => mode = Some(value.into_owned()),
+            "hub.topic" => topic = Some(value.into_owned()),
+            "hub.challenge" => challenge = Some(value.into_owned()),
+            "hub.lease_seconds" => lease_seconds = value.parse::<u64>().ok(),
+            _ => (),
+        }
+    }
+
+    let mut db = db.lock().unwrap();
+    let feed = db.feed_by_hub_callback(callback);
+    let hub = feed.as_ref().and_then(|feed| feed.hub.clone());
+    match (mode.as_deref(), hub, challenge) {
+        (Some("subscribe"), Some(hub), Some(challenge)) if topic.as_ref() == Some(&hub.topic) => {
+            let lease = lease_seconds.map_or(DEFAULT_LEASE, Duration::from_secs);
+            let hub = Hub {
+                expires_at: Some(SystemTime::now() + lease),
+                ..hub
+            };
+            db.set_hub(&feed.unwrap().link, Some(hub));
+            Response::new(challenge.into())
+        }
+        // only callbacks we no longer know can be unsubscribed
+        (Some("unsubscribe"), None, Some(challenge)) => Response::new(challenge.into()),
+        (Some("denied"), Some(hub), _) if topic.as_ref() == Some(&hub.topic) => {
+            eprintln!("WebSub subscription denied by {}", hub.link);
+            let hub = Hub {
+                expires_at: None,
+                ..hub
+            };
+            db.set_hub(&feed.unwrap().link, Some(hub));
+            status(StatusCode::OK)
+        }
+        _ => status(StatusCode::NOT_FOUND),
+    }
+}
+
+async fn receive_content(
+    bot: Bot,
+    db: Arc<Mutex<Database>>,
+    callback: &str,
+    req: Request<Body>,
+) -> Response<Body> {
+    let feed = match db.lock().unwrap().feed_by_hub_callback(callback) {
+        Some(feed) => feed,
+        // tell the hub to stop pushing
+        None => return status(StatusCode::GONE),
+    };
+    let secret = feed.hub.as_ref().unwrap().secret.clone();
+    let signature = req
+        .headers()
+        .get("X-Hub-Signature")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned);
+    let content_type = req.headers().get(CONTENT_TYPE).cloned();
+    let body = match read_body(req.into_body()).await {
+        Ok(body) => body,
+        Err(code) => return status(code),
+    };
+
+    // content without a valid signature must be acknowledged, but ignored
+    if !signature.map_or(false, |s| verify_signature(&secret, &s, &body)) {
+        eprintln!("WebSub content with invalid signature for {}", feed.link);
+        return status(StatusCode::OK);
+    }
+    let url = match Url::parse(&feed.link) {
+        Ok(url) => url,
+        Err(_) => return status(StatusCode::OK),
+    };
+    let rss = match client::parse_feed(&body[..], &url, content_type.as_ref()) {
+        Ok(rss) => rss,
+        Err(e) => {
+            crate::print_error(e);
+            return status(StatusCode::OK);
+        }
+    };
+
+    let updates = db.lock().unwrap().update_pushed(&feed.link, rss);
+    tokio::spawn(async move {
+        if let Err(e) = crate::fetcher::push_updates(&bot, &db, &feed, updates).await {
+            crate::print_error(e);
+        }
+    });
+    status(StatusCode::OK)
+}
+
+async fn read_body(mut body: Body) -> Result<Vec<u8>, StatusCode> {
+    let size_limit = client::max_feed_size() as usize;
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| StatusCode::BAD_REQUEST)?;
+        if size_limit != 0 && buf.len() + chunk.len() > size_limit {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(buf)
+}
+
+/// `X-Hub-Signature: method=signature`
+fn verify_signature(secret: &str, header: &str, body: &[u8]) -> bool {
+    let mut parts = header.splitn(2, '=');
+    let (method, signature) = match (parts.next(), parts.next()) {
+        (Some(method), Some(signature)) => (method, signature),
+        _ => return false,
+    };
+    let algorithm = match method {
+        "sha1" => hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+        "sha256" => hmac::HMAC_SHA256,
+        "sha384" => hmac::HMAC_SHA384,
+        "sha512" => hmac::HMAC_SHA512,
+        _ => return false,
+    };
+    let signature = match decode_hex(signature) {
+        Some(signature) => signature,
+        None => return false,
+    };
+    let key = hmac::Key::new(algorithm, secret.as_bytes());
+    hmac::verify(&key, body, &signature).is_ok()
+}
+
+/// Callbacks are `<base url>/websub/<random token>`
+fn callback_url(base_url: &Url, token: &str) -> String {
+    format!(
+        "{}/{}/{}",
+        base_url.as_str().trim_end_matches('/'),
+        CALLBACK_PATH,
+        token
+    )
+}
+
+/// The path may be rewritten by a reverse proxy, only the end of it is used
+fn callback_of_path(path: &str) -> Option<String> {
+    let mut segments = path.trim_end_matches('/').rsplit('/');
+    let token = segments.next()?;
+    if segments.next()? != CALLBACK_PATH || token.is_empty() {
+        return None;
+    }
+    Some(callback_url(BASE_URL.get()?, token))
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    let mut resp = Response::default();
+    *resp.status_mut() = code;
+    resp
+}
+
+fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0; len];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("system random failed");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::data::test_util::temp_database;
+    use crate::feed::Item;
+
+    #[test]
+    fn signature() {
+        let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, b"secret");
+        let tag = hmac::sign(&key, b"body");
+        let header = format!(
+            "sha1={}",
+            tag.as_ref()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        );
+        assert!(verify_signature("secret", &header, b"body"));
+        assert!(!verify_signature("secret", &header, b"forged body"));
+        assert!(!verify_signature("another secret", &header, b"body"));
+        assert!(!verify_signature("secret", "md5=00", b"body"));
+        assert!(!verify_signature("secret", "sha1", b"body"));
+        assert!(!verify_signature("secret", "sha1=zz", b"body"));
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(decode_hex("00ff7f"), Some(vec![0, 255, 127]));
+        assert_eq!(decode_hex("0"), None);
+        assert_eq!(decode_hex("0g"), None);
+        assert_eq!(random_hex(16).len(), 32);
+    }
+
+    /// A hub which verifies every subscription before responding
+    fn start_mock_hub(requests: Arc<AtomicUsize>) -> SocketAddr {
+        let make_service = make_service_fn(move |_| {
+            let requests = requests.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let requests = requests.clone();
+                    async move {
+                        requests.fetch_add(1, Ordering::SeqCst);
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        let form: std::collections::HashMap<String, String> =
+                            form_urlencoded::parse(&body).into_owned().collect();
+                        let mut verification = Url::parse(&form["hub.callback"]).unwrap();
+                        verification
+                            .query_pairs_mut()
+                            .append_pair("hub.mode", &form["hub.mode"])
+                            .append_pair("hub.topic", &form["hub.topic"])
+                            .append_pair("hub.challenge", "challenge")
+                            .append_pair("hub.lease_seconds", "86400");
+                        let resp = reqwest::get(verification).await.unwrap();
+                        let verified =
+                            resp.status().is_success() && resp.text().await.unwrap() == "challenge";
+                        Ok::<_, Infallible>(status(if verified {
+                            StatusCode::ACCEPTED
+                        } else {
+                            StatusCode::BAD_REQUEST
+                        }))
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn mock_hub() {
+        client::init_client(
+            "rssbot_test",
+            client::ClientOptions {
+                timeout: Duration::from_secs(10),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let (_file, db) = temp_database("websub");
+        let db = Arc::new(Mutex::new(db));
+        let addr = start(
+            Bot::new("token".into()),
+            db.clone(),
+            ([127, 0, 0, 1], 0).into(),
+        )
+        .unwrap();
+        init(Url::parse(&format!("http://{}/prefix/", addr)).unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let hub_addr = start_mock_hub(requests.clone());
+
+        let link = "http://example.com/feed.xml";
+        let rss = Rss {
+            title: "title".into(),
+            source: Some("http://example.com/self.xml".into()),
+            hub: Some(format!("http://{}/", hub_addr)),
+            ..Rss::default()
+        };
+        db.lock().unwrap().subscribe(1, link, &rss);
+        subscribe_if_needed(&db, link, &rss).await;
+        let hub = db.lock().unwrap().get_feed(link).unwrap().hub.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert_eq!(hub.topic, "http://example.com/self.xml");
+        assert!(hub
+            .callback
+            .starts_with(&format!("http://{}/prefix/websub/", addr)));
+        assert!(db
+            .lock()
+            .unwrap()
+            .get_feed(link)
+            .unwrap()
+            .has_websub_lease());
+
+        // the lease is still fresh
+        subscribe_if_needed(&db, link, &rss).await;
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let http = reqwest::Client::new();
+        let push = |callback: &str, body: &'static str, signature: String| {
+            http.post(callback)
+                .header("X-Hub-Signature", signature)
+                .body(body)
+                .send()
+        };
+        let forged = r#"<rss version="2.0"><channel><title>title</title>
+            <item><title>forged</title></item></channel></rss>"#;
+        let resp = push(&hub.callback, forged, "sha1=00".into()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body = r#"<rss version="2.0"><channel><title>title</title>
+            <item><title>pushed</title></item></channel></rss>"#;
+        let key = hmac::Key::new(hmac::HMAC_SHA256, hub.secret.as_bytes());
+        let tag = hmac::sign(&key, body.as_bytes());
+        let signature = format!(
+            "sha256={}",
+            tag.as_ref()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        );
+        let resp = push(&hub.callback, body, signature).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let item = |title: &str| Rss {
+            title: "title".into(),
+            items: vec![Item {
+                title: Some(title.into()),
+                ..Item::default()
+            }],
+            ..Rss::default()
+        };
+        let mut db_ = db.lock().unwrap();
+        assert!(db_.update_pushed(link, item("pushed")).is_empty());
+        // forged content is ignored
+        assert!(!db_.update_pushed(link, item("forged")).is_empty());
+        drop(db_);
+
+        let verify = |pairs: &[(&str, &str)]| {
+            let mut url = Url::parse(&hub.callback).unwrap();
+            url.query_pairs_mut().extend_pairs(pairs);
+            http.get(url).send()
+        };
+        // denied for another topic
+        let resp = verify(&[("hub.mode", "denied"), ("hub.topic", "t")])
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert!(db
+            .lock()
+            .unwrap()
+            .get_feed(link)
+            .unwrap()
+            .has_websub_lease());
+        // a lease of a default length
+        let resp = verify(&[
+            ("hub.mode", "subscribe"),
+            ("hub.topic", &hub.topic),
+            ("hub.challenge", "hi"),
+        ])
+        .await
+        .unwrap();
+        assert_eq!(resp.text().await.unwrap(), "hi");
+        let hub_ = db.lock().unwrap().get_feed(link).unwrap().hub.unwrap();
+        assert!(hub_.expires_at.unwrap() > SystemTime::now() + RENEW_BEFORE);
+
+        let unknown = format!("http://{}/websub/unknown", addr);
+        let resp = push(&unknown, body, "sha1=00".into()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::GONE);
+        let resp = http
+            .get(&format!(
+                "{}?hub.mode=unsubscribe&hub.topic=t&hub.challenge=bye",
+                unknown
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.text().await.unwrap(), "bye");
+        let resp = http
+            .get(&format!(
+                "{}?hub.mode=unsubscribe&hub.topic=t&hub.challenge=bye",
+                hub.callback
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+}